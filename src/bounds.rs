@@ -0,0 +1,167 @@
+use crate::state::PlayerState;
+use tf_demo_parser::demo::parser::gamestateanalyser::{Team, World};
+use tf_demo_parser::demo::vector::Vector;
+
+// extra space around the observed positions, so nothing ends up exactly on the edge
+const PADDING: f32 = 128.0;
+
+/// Bounding box of all positions seen in the demo
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    min: Vector,
+    max: Vector,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Bounds {
+            min: Vector {
+                x: f32::INFINITY,
+                y: f32::INFINITY,
+                z: f32::INFINITY,
+            },
+            max: Vector {
+                x: f32::NEG_INFINITY,
+                y: f32::NEG_INFINITY,
+                z: f32::NEG_INFINITY,
+            },
+        }
+    }
+}
+
+impl Bounds {
+    pub fn add(&mut self, position: Vector) {
+        self.min.x = self.min.x.min(position.x);
+        self.min.y = self.min.y.min(position.y);
+        self.min.z = self.min.z.min(position.z);
+        self.max.x = self.max.x.max(position.x);
+        self.max.y = self.max.y.max(position.y);
+        self.max.z = self.max.z.max(position.z);
+    }
+
+    /// Add the position of a player if they're alive and playing, spectators, SourceTV and players
+    /// that haven't spawned yet all sit at the map origin
+    pub fn add_player(&mut self, player: &PlayerState) {
+        if player.health > 0 && matches!(player.team, Team::Red | Team::Blue) {
+            self.add(player.position);
+        }
+    }
+
+    /// World with the observed bounds plus some padding, or `None` if no positions have been seen
+    pub fn world(&self) -> Option<World> {
+        if self.min.x > self.max.x {
+            return None;
+        }
+        Some(World {
+            boundary_min: Vector {
+                x: self.min.x - PADDING,
+                y: self.min.y - PADDING,
+                z: self.min.z - PADDING,
+            },
+            boundary_max: Vector {
+                x: self.max.x + PADDING,
+                y: self.max.y + PADDING,
+                z: self.max.z + PADDING,
+            },
+        })
+    }
+}
+
+/// Size of the smallest step between packed positions on each axis, in game units
+pub fn precision(world: &World) -> Vector {
+    let step = |min: f32, max: f32| (max - min) / u16::MAX as f32;
+    Vector {
+        x: step(world.boundary_min.x, world.boundary_max.x),
+        y: step(world.boundary_min.y, world.boundary_max.y),
+        z: step(world.boundary_min.z, world.boundary_max.z),
+    }
+}
+
+#[test]
+fn test_bounds_world() {
+    let mut bounds = Bounds::default();
+    assert!(bounds.world().is_none());
+
+    bounds.add(Vector {
+        x: 100.0,
+        y: -200.0,
+        z: 0.0,
+    });
+    bounds.add(Vector {
+        x: -50.0,
+        y: 300.0,
+        z: 10.0,
+    });
+
+    let world = bounds.world().unwrap();
+    assert_eq!(
+        Vector {
+            x: -50.0 - PADDING,
+            y: -200.0 - PADDING,
+            z: -PADDING,
+        },
+        world.boundary_min
+    );
+    assert_eq!(
+        Vector {
+            x: 100.0 + PADDING,
+            y: 300.0 + PADDING,
+            z: 10.0 + PADDING,
+        },
+        world.boundary_max
+    );
+}
+
+#[test]
+fn test_bounds_ignore_spectators() {
+    let mut bounds = Bounds::default();
+    bounds.add_player(&PlayerState {
+        position: Vector {
+            x: 1000.0,
+            y: 2000.0,
+            z: 100.0,
+        },
+        health: 125,
+        team: Team::Red,
+        ..PlayerState::default()
+    });
+    let world = bounds.world().unwrap();
+
+    // spectator and a dead player, both at the origin
+    bounds.add_player(&PlayerState {
+        health: 125,
+        team: Team::Spectator,
+        ..PlayerState::default()
+    });
+    bounds.add_player(&PlayerState {
+        team: Team::Blue,
+        ..PlayerState::default()
+    });
+    let widened = bounds.world().unwrap();
+    assert_eq!(world.boundary_min, widened.boundary_min);
+    assert_eq!(world.boundary_max, widened.boundary_max);
+}
+
+#[test]
+fn test_precision() {
+    let world = World {
+        boundary_min: Vector {
+            x: -65535.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        boundary_max: Vector {
+            x: 65535.0,
+            y: 65535.0,
+            z: 0.0,
+        },
+    };
+    assert_eq!(
+        Vector {
+            x: 2.0,
+            y: 1.0,
+            z: 0.0,
+        },
+        precision(&world)
+    );
+}
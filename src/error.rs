@@ -0,0 +1,120 @@
+use bitbuffer::BitError;
+use std::fmt;
+use tf_demo_parser::ParseError;
+use wasm_bindgen::prelude::*;
+
+/// Whether the parser ran out of data, as opposed to finding invalid data
+pub fn is_out_of_data(error: &ParseError) -> bool {
+    matches!(error, ParseError::ReadError(BitError::NotEnoughData { .. }))
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The demo ends before the parser expected it to
+    Truncated,
+    NotADemo,
+    UnsupportedProtocol,
+    MissingWorld,
+    CorruptPacket,
+    Cancelled,
+}
+
+#[derive(Debug)]
+pub enum DemoError {
+    Parse {
+        error: ParseError,
+        tick: u32,
+        offset: usize,
+        /// Whether any ticks have been parsed before the error
+        partial: bool,
+    },
+    NotADemo,
+    UnsupportedProtocol(u32),
+    MissingWorld,
+    /// The progress callback asked for the parse to be stopped
+    Cancelled,
+}
+
+impl DemoError {
+    pub fn kind(&self) -> ParseErrorKind {
+        match self {
+            // running out of data while reading a packet means the demo was cut off
+            DemoError::Parse { error, .. } if is_out_of_data(error) => ParseErrorKind::Truncated,
+            DemoError::Parse { .. } => ParseErrorKind::CorruptPacket,
+            DemoError::NotADemo => ParseErrorKind::NotADemo,
+            DemoError::UnsupportedProtocol(_) => ParseErrorKind::UnsupportedProtocol,
+            DemoError::MissingWorld => ParseErrorKind::MissingWorld,
+            DemoError::Cancelled => ParseErrorKind::Cancelled,
+        }
+    }
+}
+
+impl fmt::Display for DemoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DemoError::Parse {
+                error,
+                tick,
+                offset,
+                ..
+            } => write!(f, "{} at tick {} (byte {})", error, tick, offset),
+            DemoError::NotADemo => write!(f, "File is not a demo"),
+            DemoError::UnsupportedProtocol(version) => {
+                write!(f, "Unsupported demo protocol version {}", version)
+            }
+            DemoError::MissingWorld => write!(f, "No world defined in demo"),
+            DemoError::Cancelled => write!(f, "Parsing was cancelled"),
+        }
+    }
+}
+
+/// Error thrown to js when parsing fails
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct DemoParseError {
+    pub kind: ParseErrorKind,
+    pub message: String,
+    /// The demo tick being parsed when the error occurred
+    pub tick: Option<u32>,
+    pub byte_offset: Option<u32>,
+    /// Whether the ticks parsed before the error can still be used, see `parse_demo_lenient`
+    pub partial_available: bool,
+}
+
+impl From<DemoError> for DemoParseError {
+    fn from(error: DemoError) -> Self {
+        let (tick, byte_offset, partial_available) = match &error {
+            DemoError::Parse {
+                tick,
+                offset,
+                partial,
+                ..
+            } => (Some(*tick), Some(*offset as u32), *partial),
+            _ => (None, None, false),
+        };
+        DemoParseError {
+            kind: error.kind(),
+            message: error.to_string(),
+            tick,
+            byte_offset,
+            partial_available,
+        }
+    }
+}
+
+#[test]
+fn test_error_kind() {
+    assert_eq!(ParseErrorKind::MissingWorld, DemoError::MissingWorld.kind());
+
+    let error = DemoParseError::from(DemoError::Parse {
+        error: ParseError::InvalidPacketType(42),
+        tick: 1234,
+        offset: 5678,
+        partial: true,
+    });
+    assert_eq!(ParseErrorKind::CorruptPacket, error.kind);
+    assert_eq!(Some(1234), error.tick);
+    assert_eq!(Some(5678), error.byte_offset);
+    assert!(error.partial_available);
+}
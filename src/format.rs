@@ -0,0 +1,203 @@
+use crate::state::{BuildingState, PlayerState, ProjectileState};
+use tf_demo_parser::demo::parser::gamestateanalyser::World;
+use tf_demo_parser::demo::vector::Vector;
+
+/// Bumped whenever the layout of the header or any of the packets changes
+pub const FORMAT_VERSION: u16 = 10;
+pub const MAGIC: [u8; 4] = *b"TFDV";
+
+/// Header written in front of the packed tick data.
+///
+/// All values are little endian, the layout is:
+///
+/// | offset | type     | field                     |
+/// |--------|----------|---------------------------|
+/// | 0      | [u8; 4]  | magic (`TFDV`)            |
+/// | 4      | u16      | format version            |
+/// | 6      | u16      | header size               |
+/// | 8      | u32      | tick count                |
+/// | 12     | u32      | player count              |
+/// | 16     | u32      | building count            |
+/// | 20     | u32      | projectile count          |
+/// | 24     | u32      | player section offset     |
+/// | 28     | u32      | building section offset   |
+/// | 32     | u32      | projectile section offset |
+/// | 36     | u8       | player packet size        |
+/// | 37     | u8       | building packet size      |
+/// | 38     | u8       | projectile packet size    |
+/// | 39     | u8       | reserved                  |
+/// | 40     | f32 x 3  | world boundary min        |
+/// | 52     | f32 x 3  | world boundary max        |
+///
+/// Each section contains `count` entities, each entity is `tick_count * packet_size` bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataHeader {
+    pub version: u16,
+    pub tick_count: u32,
+    pub player_count: u32,
+    pub building_count: u32,
+    pub projectile_count: u32,
+    pub player_packet_size: u8,
+    pub building_packet_size: u8,
+    pub projectile_packet_size: u8,
+    pub boundary_min: Vector,
+    pub boundary_max: Vector,
+}
+
+impl DataHeader {
+    pub const SIZE: usize = 64;
+
+    pub fn new(
+        tick_count: u32,
+        player_count: u32,
+        building_count: u32,
+        projectile_count: u32,
+        boundary_min: Vector,
+        boundary_max: Vector,
+    ) -> Self {
+        DataHeader {
+            version: FORMAT_VERSION,
+            tick_count,
+            player_count,
+            building_count,
+            projectile_count,
+            player_packet_size: PlayerState::PACKET_SIZE as u8,
+            building_packet_size: BuildingState::PACKET_SIZE as u8,
+            projectile_packet_size: ProjectileState::PACKET_SIZE as u8,
+            boundary_min,
+            boundary_max,
+        }
+    }
+
+    pub fn world(&self) -> World {
+        World {
+            boundary_min: self.boundary_min,
+            boundary_max: self.boundary_max,
+        }
+    }
+
+    pub fn player_offset(&self) -> u32 {
+        Self::SIZE as u32
+    }
+
+    pub fn building_offset(&self) -> u32 {
+        self.player_offset() + self.player_count * self.tick_count * self.player_packet_size as u32
+    }
+
+    pub fn projectile_offset(&self) -> u32 {
+        self.building_offset()
+            + self.building_count * self.tick_count * self.building_packet_size as u32
+    }
+
+    pub fn pack(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6..8].copy_from_slice(&(Self::SIZE as u16).to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.tick_count.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.player_count.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.building_count.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.projectile_count.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.player_offset().to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.building_offset().to_le_bytes());
+        bytes[32..36].copy_from_slice(&self.projectile_offset().to_le_bytes());
+        bytes[36] = self.player_packet_size;
+        bytes[37] = self.building_packet_size;
+        bytes[38] = self.projectile_packet_size;
+        for (i, val) in [
+            self.boundary_min.x,
+            self.boundary_min.y,
+            self.boundary_min.z,
+            self.boundary_max.x,
+            self.boundary_max.y,
+            self.boundary_max.z,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let start = 40 + i * 4;
+            bytes[start..start + 4].copy_from_slice(&val.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn unpack(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE || bytes[0..4] != MAGIC {
+            return None;
+        }
+
+        let u16_at = |start: usize| u16::from_le_bytes([bytes[start], bytes[start + 1]]);
+        let u32_at = |start: usize| {
+            u32::from_le_bytes([
+                bytes[start],
+                bytes[start + 1],
+                bytes[start + 2],
+                bytes[start + 3],
+            ])
+        };
+        let f32_at = |start: usize| f32::from_bits(u32_at(start));
+
+        Some(DataHeader {
+            version: u16_at(4),
+            tick_count: u32_at(8),
+            player_count: u32_at(12),
+            building_count: u32_at(16),
+            projectile_count: u32_at(20),
+            player_packet_size: bytes[36],
+            building_packet_size: bytes[37],
+            projectile_packet_size: bytes[38],
+            boundary_min: Vector {
+                x: f32_at(40),
+                y: f32_at(44),
+                z: f32_at(48),
+            },
+            boundary_max: Vector {
+                x: f32_at(52),
+                y: f32_at(56),
+                z: f32_at(60),
+            },
+        })
+    }
+}
+
+#[test]
+fn test_header_packing() {
+    let input = DataHeader::new(
+        1000,
+        18,
+        4,
+        12,
+        Vector {
+            x: -10000.0,
+            y: -10000.0,
+            z: -100.0,
+        },
+        Vector {
+            x: 10000.0,
+            y: 10000.0,
+            z: 100.0,
+        },
+    );
+
+    let bytes = input.pack();
+    assert_eq!(&bytes[0..4], b"TFDV");
+    assert_eq!(
+        u16::from_le_bytes([bytes[6], bytes[7]]) as usize,
+        DataHeader::SIZE
+    );
+
+    let unpacked = DataHeader::unpack(&bytes).unwrap();
+    assert_eq!(input, unpacked);
+    assert_eq!(FORMAT_VERSION, unpacked.version);
+
+    assert_eq!(
+        DataHeader::SIZE + 18 * 1000 * PlayerState::PACKET_SIZE,
+        unpacked.building_offset() as usize
+    );
+    assert_eq!(
+        unpacked.building_offset() as usize + 4 * 1000 * BuildingState::PACKET_SIZE,
+        unpacked.projectile_offset() as usize
+    );
+
+    assert_eq!(None, DataHeader::unpack(&bytes[0..10]));
+}
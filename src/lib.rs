@@ -1,34 +1,54 @@
 #![macro_use]
 
-use crate::state::{ParsedDemo, SearchableEvent};
-use js_sys::Function;
+use crate::error::{DemoError, DemoParseError};
+use crate::format::DataHeader;
+use crate::query::{BuildingTickState, PlayerTickState, ProjectileTickState};
+use crate::roster::{NameChange, PlayerIds, PlayerSession, RosterEntry};
+use crate::slots::EntityLifetime;
+use crate::state::{DemoEvent, ParsedDemo, SearchableEvent};
+use crate::stream::StreamingParser;
+use crate::weapons::WeaponInfo;
+use js_sys::{Function, Uint8Array};
+use std::collections::BTreeMap;
 use tf_demo_parser::demo::header::Header;
-use tf_demo_parser::demo::parser::analyser::UserInfo;
-use tf_demo_parser::demo::parser::gamestateanalyser::{GameStateAnalyser, World};
+use tf_demo_parser::demo::message::packetentities::EntityId;
+use tf_demo_parser::demo::parser::gamestateanalyser::{UserId, World};
 use tf_demo_parser::demo::vector::Vector;
-use tf_demo_parser::{Demo, DemoParser, ParseError};
 use wasm_bindgen::prelude::*;
 
+mod bounds;
+mod error;
+mod format;
+mod query;
+mod roster;
+mod slots;
 mod state;
+mod stream;
+mod weapons;
 
 #[wasm_bindgen]
-#[derive(Debug, Clone, Copy)]
-pub struct XY {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XYZ {
     pub x: f32,
     pub y: f32,
+    pub z: f32,
 }
 
-impl From<Vector> for XY {
+impl From<Vector> for XYZ {
     fn from(vec: Vector) -> Self {
-        XY { x: vec.x, y: vec.y }
+        XYZ {
+            x: vec.x,
+            y: vec.y,
+            z: vec.z,
+        }
     }
 }
 
 #[wasm_bindgen]
 #[derive(Debug, Clone, Copy)]
 pub struct WorldBoundaries {
-    pub boundary_min: XY,
-    pub boundary_max: XY,
+    pub boundary_min: XYZ,
+    pub boundary_max: XYZ,
 }
 
 impl From<World> for WorldBoundaries {
@@ -40,6 +60,67 @@ impl From<World> for WorldBoundaries {
     }
 }
 
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct KillInfo {
+    pub tick: u32,
+    pub attacker_id: Option<u32>,
+    pub assister_id: Option<u32>,
+    pub victim_id: Option<u32>,
+    pub weapon: String,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct PlayerInfo {
+    pub id: u32,
+    pub name: String,
+    pub user_id: u16,
+    pub entity_id: u32,
+    pub steam_id: String,
+    pub sessions: Vec<PlayerSession>,
+    pub names: Vec<NameChange>,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct DemoHeader {
+    pub demo_type: String,
+    /// Demo protocol version
+    pub version: u32,
+    /// Network protocol version
+    pub protocol: u32,
+    pub server: String,
+    /// Name of the player that recorded the demo, or the name of the stv bot
+    pub nick: String,
+    pub map: String,
+    pub game: String,
+    /// Duration in seconds
+    pub duration: f32,
+    pub ticks: u32,
+    pub frames: u32,
+    /// Length of the signon data in bytes
+    pub signon: u32,
+}
+
+impl From<&Header> for DemoHeader {
+    fn from(header: &Header) -> Self {
+        DemoHeader {
+            demo_type: header.demo_type.clone(),
+            version: header.version,
+            protocol: header.protocol,
+            server: header.server.clone(),
+            nick: header.nick.clone(),
+            map: header.map.clone(),
+            game: header.game.clone(),
+            duration: header.duration,
+            ticks: header.ticks,
+            frames: header.frames,
+            signon: header.signon,
+        }
+    }
+}
+
 #[wasm_bindgen]
 pub struct FlatState {
     pub player_count: usize,
@@ -48,39 +129,73 @@ pub struct FlatState {
     pub boundaries: WorldBoundaries,
     pub interval_per_tick: f32,
     pub tick_count: u32,
+    /// Size of the smallest step between packed positions on each axis, in game units
+    pub precision: XYZ,
+    /// The demo couldn't be parsed to the end, only ticks up to `last_good_tick` are available
+    pub truncated: bool,
+    pub last_good_tick: u32,
     kill_ticks: Box<[u32]>,
-    attackers: Box<[u8]>,
-    assisters: Box<[u8]>,
-    victims: Box<[u8]>,
+    attackers: Box<[i32]>,
+    assisters: Box<[i32]>,
+    victims: Box<[i32]>,
     weapons: Vec<String>,
-    player_info: Vec<UserInfo>,
+    roster: Vec<RosterEntry>,
+    player_ids: PlayerIds,
     events: Vec<SearchableEvent>,
+    building_lifetimes: Vec<EntityLifetime>,
+    projectile_lifetimes: Vec<EntityLifetime>,
+    weapon_table: Vec<WeaponInfo>,
+    heal_ticks: BTreeMap<(usize, usize), u32>,
+    projectile_counts: Vec<u32>,
+    snapshot_ticks: Vec<u32>,
     data: Box<[u8]>,
     header: Header,
 }
 
 impl FlatState {
     pub fn new(parsed: ParsedDemo, world: World) -> Self {
+        let interval_per_tick = parsed.interval_per_tick();
         let ParsedDemo {
             players,
             header,
             buildings,
             projectiles,
-            max_building_count,
-            max_projectile_count,
+            building_lifetimes,
+            projectile_lifetimes,
+            weapons,
+            heal_ticks,
+            mut projectile_counts,
+            player_ids,
             tick,
+            truncated,
+            snapshot_ticks,
             ..
         } = parsed;
 
         let player_count = players.len();
-        let building_count = max_building_count;
-        let projectile_count = max_projectile_count;
-
-        let flat: Vec<_> = players
+        projectile_counts.resize(player_count, 0);
+        let building_count = buildings.len();
+        let projectile_count = projectiles.len();
+
+        let data_header = DataHeader::new(
+            tick as u32,
+            player_count as u32,
+            building_count as u32,
+            projectile_count as u32,
+            world.boundary_min,
+            world.boundary_max,
+        );
+
+        let flat: Vec<_> = data_header
+            .pack()
             .into_iter()
-            .chain(buildings)
-            .chain(projectiles)
-            .flat_map(Vec::into_iter)
+            .chain(
+                players
+                    .into_iter()
+                    .chain(buildings)
+                    .chain(projectiles)
+                    .flat_map(Vec::into_iter),
+            )
             .collect();
 
         FlatState {
@@ -88,70 +203,253 @@ impl FlatState {
             building_count,
             projectile_count,
             tick_count: tick as u32,
+            precision: bounds::precision(&world).into(),
+            truncated,
+            last_good_tick: (tick as u32).saturating_sub(1),
             boundaries: world.into(),
-            interval_per_tick: header.duration / (header.ticks as f32),
+            interval_per_tick,
             data: flat.into_boxed_slice(),
             kill_ticks: parsed.kills.iter().map(|kill| kill.tick.into()).collect(),
             attackers: parsed
                 .kills
                 .iter()
-                .map(|kill| kill.attacker_id as u8)
+                .map(|kill| player_id_or_none(&player_ids, kill.attacker_id))
                 .collect(),
             assisters: parsed
                 .kills
                 .iter()
-                .map(|kill| kill.assister_id as u8)
+                .map(|kill| player_id_or_none(&player_ids, kill.assister_id))
                 .collect(),
             victims: parsed
                 .kills
                 .iter()
-                .map(|kill| kill.victim_id as u8)
+                .map(|kill| player_id_or_none(&player_ids, kill.victim_id))
                 .collect(),
             weapons: parsed.kills.into_iter().map(|kill| kill.weapon).collect(),
-            player_info: parsed.player_info,
+            roster: parsed.roster_entries,
+            player_ids,
             events: parsed.events,
+            building_lifetimes,
+            projectile_lifetimes,
+            weapon_table: weapons.into_weapons(),
+            heal_ticks,
+            projectile_counts,
+            snapshot_ticks,
             header,
         }
     }
 }
 
+/// Player id for a user id, with -1 for no player (e.g. kills by the world)
+fn player_id_or_none(player_ids: &PlayerIds, user_id: u16) -> i32 {
+    player_ids
+        .by_user_id(UserId::from(user_id))
+        .map(|id| id as i32)
+        .unwrap_or(-1)
+}
+
+/// Parse a full demo, calling `progress` with the percentage parsed so far.
+///
+/// `progress` is called every few ticks, not only when the percentage changes. The parse can be
+/// aborted by returning `false` from `progress`, in which case a
+/// `DemoParseError` with the `Cancelled` kind is thrown.
+///
+/// Positions can only be packed once the map boundaries are known, ticks before that are kept in
+/// memory unpacked. For demos without any map boundaries that means the whole demo is kept
+/// unpacked until parsing is done, after which the boundaries are taken from the positions seen.
 #[wasm_bindgen]
-pub fn parse_demo(buffer: Box<[u8]>, progress: &Function) -> Result<FlatState, JsValue> {
-    let (parsed, world) =
-        parse_demo_inner(&buffer, progress).map_err(|e| JsValue::from(e.to_string()))?;
+pub fn parse_demo(buffer: Box<[u8]>, progress: &Function) -> Result<FlatState, DemoParseError> {
+    parse_demo_with_options(buffer, progress, false, false)
+}
 
-    let world = world.ok_or_else(|| JsValue::from_str("No world defined in demo"))?;
+/// Parse a demo like `parse_demo`, but if the demo is truncated or corrupt, return everything up
+/// to the point of the error with the `truncated` flag set instead of failing.
+///
+/// Errors before any ticks could be parsed are still thrown.
+#[wasm_bindgen]
+pub fn parse_demo_lenient(
+    buffer: Box<[u8]>,
+    progress: &Function,
+) -> Result<FlatState, DemoParseError> {
+    parse_demo_with_options(buffer, progress, true, false)
+}
+
+/// Parse a demo with all options.
+///
+/// - `lenient`: see `parse_demo_lenient`
+/// - `tight_bounds`: pack positions against the area the entities have actually been in instead
+///   of the map boundaries, which can be a lot more precise (see `FlatState.precision`) but keeps
+///   the whole demo in memory in unpacked form until parsing is done
+#[wasm_bindgen]
+pub fn parse_demo_with_options(
+    buffer: Box<[u8]>,
+    progress: &Function,
+    lenient: bool,
+    tight_bounds: bool,
+) -> Result<FlatState, DemoParseError> {
+    let options = ParseOptions {
+        lenient,
+        tight_bounds,
+    };
+    let progress = |percentage: f32| {
+        let result = progress.call1(&JsValue::null(), &percentage.into());
+        !result.is_ok_and(|result| result.as_bool() == Some(false))
+    };
+    let parsed = parse_demo_inner(buffer.into_vec(), progress, options)?;
+
+    Ok(flat_state(parsed)?)
+}
+
+fn flat_state(parsed: ParsedDemo) -> Result<FlatState, DemoError> {
+    let world = parsed.world.clone().ok_or(DemoError::MissingWorld)?;
 
     Ok(FlatState::new(parsed, world))
 }
 
+/// Start parsing a demo that is still being downloaded, feed it with `push_demo_chunk`
+#[wasm_bindgen]
+pub fn create_streaming_parser() -> StreamingParser {
+    StreamingParser::new()
+}
+
+/// Add the next chunk of the demo, returns the last tick that has been parsed so far
+#[wasm_bindgen]
+pub fn push_demo_chunk(parser: &mut StreamingParser, chunk: &[u8]) -> Result<u32, DemoParseError> {
+    parser.push(chunk)?;
+    Ok(parser.tick() as u32)
+}
+
+/// State with everything parsed so far, so playback can start before the download is done.
+///
+/// This copies everything parsed so far, so it should be called once when starting playback, not
+/// after every chunk. Returns `undefined` if not enough of the demo has been parsed yet.
+#[wasm_bindgen]
+pub fn get_streaming_state(parser: &StreamingParser) -> Option<FlatState> {
+    flat_state(parser.snapshot()?).ok()
+}
+
+/// Start parsing a demo that is parsed a limited number of ticks at a time with `step_parser`,
+/// so the parse can be spread out without blocking the main thread
+#[wasm_bindgen]
+pub fn create_stepped_parser(buffer: Box<[u8]>) -> StreamingParser {
+    StreamingParser::with_data(buffer.into_vec())
+}
+
+/// Parse at most `max_ticks` more ticks, returns `true` once the whole demo has been parsed
+#[wasm_bindgen]
+pub fn step_parser(parser: &mut StreamingParser, max_ticks: u32) -> Result<bool, DemoParseError> {
+    Ok(parser.step(max_ticks)?)
+}
+
+/// Percentage of the demo parsed so far
+#[wasm_bindgen]
+pub fn get_parser_progress(parser: &StreamingParser) -> f32 {
+    parser.progress()
+}
+
+/// Parse the remaining data once the whole demo has been pushed, or finish a stepped parse
+#[wasm_bindgen]
+pub fn finish_streaming_parser(parser: StreamingParser) -> Result<FlatState, DemoParseError> {
+    Ok(flat_state(parser.finish()?)?)
+}
+
 #[wasm_bindgen]
 pub fn get_data(state: FlatState) -> Box<[u8]> {
     state.data
 }
 
+/// Copy of the packed data that leaves the state usable
+#[wasm_bindgen]
+pub fn get_data_copy(state: &FlatState) -> Box<[u8]> {
+    state.data.clone()
+}
+
+/// View directly into the packed data in wasm memory, without copying or consuming the state.
+///
+/// The view is invalidated when the state is freed or the wasm memory grows, so any
+/// allocation (including calling other functions from this module) can invalidate it.
+/// Use `get_data_copy` if the data needs to outlive that.
+#[wasm_bindgen]
+pub fn get_data_view(state: &FlatState) -> Uint8Array {
+    // safety: the data is never modified after parsing, and the caller is warned about the
+    // lifetime of the view above
+    unsafe { Uint8Array::view(&state.data) }
+}
+
+/// Decoded state of all connected players at a tick
+#[wasm_bindgen]
+pub fn get_players_at(state: &FlatState, tick: u32) -> Vec<PlayerTickState> {
+    query::players_at(&state.data, tick)
+}
+
+/// Decoded state of all buildings at a tick
+#[wasm_bindgen]
+pub fn get_buildings_at(state: &FlatState, tick: u32) -> Vec<BuildingTickState> {
+    query::buildings_at(&state.data, tick)
+}
+
+/// Decoded state of all projectiles at a tick
+#[wasm_bindgen]
+pub fn get_projectiles_at(state: &FlatState, tick: u32) -> Vec<ProjectileTickState> {
+    query::projectiles_at(&state.data, tick)
+}
+
+/// Players at a fractional tick, with positions and view angles interpolated between snapshots.
+///
+/// The tick is not a time, to get the tick for a playback time in seconds divide it by
+/// `interval_per_tick`
+#[wasm_bindgen]
+pub fn get_players_interpolated(state: &FlatState, tick: f32) -> Vec<PlayerTickState> {
+    query::players_interpolated(&state.data, &state.snapshot_ticks, tick)
+}
+
+/// Buildings at a fractional tick, with positions and angles interpolated between snapshots
+#[wasm_bindgen]
+pub fn get_buildings_interpolated(state: &FlatState, tick: f32) -> Vec<BuildingTickState> {
+    query::buildings_interpolated(&state.data, &state.snapshot_ticks, tick)
+}
+
+/// Projectiles at a fractional tick, with positions and angles interpolated between snapshots
+#[wasm_bindgen]
+pub fn get_projectiles_interpolated(state: &FlatState, tick: f32) -> Vec<ProjectileTickState> {
+    query::projectiles_interpolated(&state.data, &state.snapshot_ticks, tick)
+}
+
+/// The ticks that contain a new snapshot of the game state, other ticks repeat the snapshot
+/// before them
+#[wasm_bindgen]
+pub fn get_snapshot_ticks(state: &FlatState) -> Box<[u32]> {
+    state.snapshot_ticks.clone().into_boxed_slice()
+}
+
 #[wasm_bindgen]
 pub fn get_map(state: &FlatState) -> String {
     state.header.map.clone()
 }
 
+/// All fields from the demo header
+#[wasm_bindgen]
+pub fn get_header(state: &FlatState) -> DemoHeader {
+    DemoHeader::from(&state.header)
+}
+
 #[wasm_bindgen]
 pub fn get_kill_ticks(state: &FlatState) -> Box<[u32]> {
     state.kill_ticks.clone()
 }
 
 #[wasm_bindgen]
-pub fn get_attacker_ids(state: &FlatState) -> Box<[u8]> {
+pub fn get_attacker_ids(state: &FlatState) -> Box<[i32]> {
     state.attackers.clone()
 }
 
 #[wasm_bindgen]
-pub fn get_assister_ids(state: &FlatState) -> Box<[u8]> {
+pub fn get_assister_ids(state: &FlatState) -> Box<[i32]> {
     state.assisters.clone()
 }
 
 #[wasm_bindgen]
-pub fn get_victim_ids(state: &FlatState) -> Box<[u8]> {
+pub fn get_victim_ids(state: &FlatState) -> Box<[i32]> {
     state.victims.clone()
 }
 
@@ -160,24 +458,118 @@ pub fn get_weapon(state: &FlatState, kill_id: usize) -> String {
     state.weapons[kill_id].clone()
 }
 
+/// Weapons referenced by the active weapon in the packed player state, offset by one
+#[wasm_bindgen]
+pub fn get_weapon_table(state: &FlatState) -> Vec<WeaponInfo> {
+    state.weapon_table.clone()
+}
+
+/// Number of ticks the medic has spent healing each player, indexed by player id
+#[wasm_bindgen]
+pub fn get_heal_ticks(state: &FlatState, medic_id: usize) -> Box<[u32]> {
+    let mut ticks = vec![0; state.player_count];
+    if medic_id >= state.player_count {
+        return ticks.into_boxed_slice();
+    }
+    for ((_, target), count) in state.heal_ticks.range((medic_id, 0)..(medic_id + 1, 0)) {
+        ticks[*target] = *count;
+    }
+    ticks.into_boxed_slice()
+}
+
+/// Number of projectiles fired by each player, indexed by player id
+#[wasm_bindgen]
+pub fn get_projectile_counts(state: &FlatState) -> Box<[u32]> {
+    state.projectile_counts.clone().into_boxed_slice()
+}
+
+/// The full killfeed in a single call
+#[wasm_bindgen]
+pub fn get_kills(state: &FlatState) -> Vec<KillInfo> {
+    let id = |id: i32| u32::try_from(id).ok();
+    (0..state.kill_ticks.len())
+        .map(|kill_id| KillInfo {
+            tick: state.kill_ticks[kill_id],
+            attacker_id: id(state.attackers[kill_id]),
+            assister_id: id(state.assisters[kill_id]),
+            victim_id: id(state.victims[kill_id]),
+            weapon: state.weapons[kill_id].clone(),
+        })
+        .collect()
+}
+
+/// All players in a single call, indexed by player id
+#[wasm_bindgen]
+pub fn get_players(state: &FlatState) -> Vec<PlayerInfo> {
+    state
+        .roster
+        .iter()
+        .enumerate()
+        .map(|(id, entry)| PlayerInfo {
+            id: id as u32,
+            name: entry.info.name.clone(),
+            user_id: entry.info.user_id.into(),
+            entity_id: entry.info.entity_id.into(),
+            steam_id: entry.info.steam_id.clone(),
+            sessions: entry.sessions.clone(),
+            names: entry.names.clone(),
+        })
+        .collect()
+}
+
 #[wasm_bindgen]
 pub fn get_player_name(state: &FlatState, player_id: usize) -> String {
-    state.player_info[player_id].name.clone()
+    state.roster[player_id].info.name.clone()
 }
 
 #[wasm_bindgen]
 pub fn get_player_entity_id(state: &FlatState, player_id: usize) -> u32 {
-    state.player_info[player_id].entity_id.into()
+    state.roster[player_id].info.entity_id.into()
 }
 
 #[wasm_bindgen]
 pub fn get_player_user_id(state: &FlatState, player_id: usize) -> u16 {
-    state.player_info[player_id].user_id.into()
+    state.roster[player_id].info.user_id.into()
 }
 
 #[wasm_bindgen]
 pub fn get_player_steam_id(state: &FlatState, player_id: usize) -> String {
-    state.player_info[player_id].steam_id.clone()
+    state.roster[player_id].info.steam_id.clone()
+}
+
+#[wasm_bindgen]
+pub fn get_building_lifetimes(state: &FlatState) -> Vec<EntityLifetime> {
+    state.building_lifetimes.clone()
+}
+
+#[wasm_bindgen]
+pub fn get_projectile_lifetimes(state: &FlatState) -> Vec<EntityLifetime> {
+    state.projectile_lifetimes.clone()
+}
+
+/// The connected periods of the player, a reconnect starts a new session
+#[wasm_bindgen]
+pub fn get_player_sessions(state: &FlatState, player_id: usize) -> Vec<PlayerSession> {
+    state.roster[player_id].sessions.clone()
+}
+
+#[wasm_bindgen]
+pub fn get_player_name_history(state: &FlatState, player_id: usize) -> Vec<NameChange> {
+    state.roster[player_id].names.clone()
+}
+
+/// Find the player id used by kills, events and the packed data for a user id
+#[wasm_bindgen]
+pub fn get_player_id_by_user_id(state: &FlatState, user_id: u16) -> Option<usize> {
+    state.player_ids.by_user_id(UserId::from(user_id))
+}
+
+/// Find the player id used by kills, events and the packed data for an entity id at a tick
+#[wasm_bindgen]
+pub fn get_player_id_by_entity_id(state: &FlatState, entity_id: u32, tick: u32) -> Option<usize> {
+    state
+        .player_ids
+        .by_entity_id(EntityId::from(entity_id), tick)
 }
 
 #[wasm_bindgen]
@@ -190,35 +582,57 @@ pub fn get_event(state: &FlatState, id: usize) -> String {
     serde_json::to_string(&state.events[id]).unwrap_or_default()
 }
 
+/// All events in a single call
+#[wasm_bindgen]
+pub fn get_events(state: &FlatState) -> Vec<DemoEvent> {
+    state.events.iter().map(DemoEvent::from).collect()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Keep the ticks parsed before an error instead of failing
+    pub lenient: bool,
+    /// See `ParsedDemo::tight_bounds`
+    pub tight_bounds: bool,
+}
+
+// how many ticks to parse between progress updates and checks for cancellation
+const PROGRESS_STEP_TICKS: u32 = 64;
+
+/// Parse a demo, `progress` is called regularly with the percentage parsed so far and can return
+/// `false` to cancel the parse
 pub fn parse_demo_inner(
-    buffer: &[u8],
-    progress: &Function,
-) -> Result<(ParsedDemo, Option<World>), ParseError> {
-    let demo = Demo::new(buffer);
-
-    let parser = DemoParser::new_with_analyser(demo.get_stream(), GameStateAnalyser::default());
-    let (header, mut ticker) = parser.ticker()?;
-    let total_ticks = header.ticks;
-    let mut last_progress = 0.0;
-
-    let mut parsed_demo = ParsedDemo::new(header);
-
-    while ticker.tick()? {
-        parsed_demo.push_state(ticker.state());
-        let new_progress =
-            ((u32::from(ticker.state().tick) as f32 / total_ticks as f32) * 100.0).floor();
-        if new_progress > last_progress {
-            last_progress = new_progress;
-            let _ = progress.call1(&JsValue::null(), &last_progress.into());
+    buffer: Vec<u8>,
+    mut progress: impl FnMut(f32) -> bool,
+    options: ParseOptions,
+) -> Result<ParsedDemo, DemoError> {
+    let mut parser = StreamingParser::with_data(buffer).with_tight_bounds(options.tight_bounds);
+
+    loop {
+        match parser.step(PROGRESS_STEP_TICKS) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(DemoError::Parse { partial: true, .. }) if options.lenient => {
+                return Ok(parser
+                    .finish_partial()
+                    .expect("header is parsed when a partial result is available"));
+            }
+            Err(e) => return Err(e),
         }
-    }
 
-    parsed_demo.finish(ticker.state());
+        // called after every step, so the parse can be cancelled even if the percentage is stuck
+        if !progress(parser.progress().floor()) {
+            return Err(DemoError::Cancelled);
+        }
+    }
 
-    let state = ticker.into_state();
+    parser.finish()
+}
 
-    parsed_demo.kills = state.kills;
-    Ok((parsed_demo, state.world))
+/// Direction vector for a player's view, for drawing aim lines
+#[wasm_bindgen]
+pub fn view_direction(yaw: f32, pitch: f32) -> XYZ {
+    state::view_direction(yaw, pitch).into()
 }
 
 // This is like the `main` function, except for JavaScript.
@@ -226,3 +640,26 @@ pub fn parse_demo_inner(
 pub fn main_js() -> Result<(), JsValue> {
     Ok(())
 }
+
+#[test]
+fn test_lenient_truncated() {
+    let mut data = stream::demo_header(0);
+    data.extend_from_slice(&stream::sync_tick_packet(5));
+    data.extend_from_slice(&stream::sync_tick_packet(10));
+    // cut off in the middle of a packet, and without an updated header like after a server crash
+    data.extend_from_slice(&stream::sync_tick_packet(15)[..2]);
+
+    let options = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+    let parsed = parse_demo_inner(data.clone(), |_| true, options).unwrap();
+    assert!(parsed.truncated);
+    assert!(parsed.tick > 0);
+    assert_eq!(0, parsed.header.ticks);
+    assert!(parsed.interval_per_tick().is_finite());
+    assert!(parsed.interval_per_tick() > 0.0);
+
+    let strict = parse_demo_inner(data, |_| true, ParseOptions::default());
+    assert!(matches!(strict, Err(DemoError::Parse { .. })));
+}
@@ -0,0 +1,417 @@
+use crate::format::DataHeader;
+use crate::state::{unpack_player_index, BuildingState, PlayerState, ProjectileState};
+use crate::XYZ;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerTickState {
+    pub id: u32,
+    pub position: XYZ,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub health: u16,
+    pub team: u8,
+    pub class: u8,
+    pub charge: u8,
+    /// Bitfield of `PlayerCondition`
+    pub conditions: u8,
+    /// Index into the weapon table
+    pub active_weapon: Option<u32>,
+    pub heal_target: Option<u32>,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildingTickState {
+    pub slot: u32,
+    pub position: XYZ,
+    pub yaw: f32,
+    pub health: u16,
+    pub team: u8,
+    pub building_type: u8,
+    pub level: u8,
+    pub builder: Option<u32>,
+    /// Bitfield of `BuildingStatus`
+    pub flags: u8,
+    pub target: Option<u32>,
+    pub shells: u8,
+    pub rockets: u8,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProjectileTickState {
+    pub slot: u32,
+    pub position: XYZ,
+    pub yaw: f32,
+    pub team: u8,
+    pub projectile_type: u8,
+    pub critical: bool,
+    pub owner: Option<u32>,
+}
+
+fn player_ref(packed: u8) -> Option<u32> {
+    unpack_player_index(packed).map(|index| index as u32)
+}
+
+/// Iterate over the packets of all entities in a section at the given tick, skipping empty slots
+fn packets_at<const N: usize>(
+    data: &[u8],
+    header: &DataHeader,
+    offset: u32,
+    count: u32,
+    tick: u32,
+) -> impl Iterator<Item = (u32, [u8; N])> + '_ {
+    let tick_count = header.tick_count as usize;
+    let valid_tick = tick < header.tick_count;
+    (0..count).filter_map(move |index| {
+        if !valid_tick {
+            return None;
+        }
+        let start = offset as usize + (index as usize * tick_count + tick as usize) * N;
+        let packet: [u8; N] = data.get(start..start + N)?.try_into().ok()?;
+        // empty slots are filled with zeros
+        if packet.iter().all(|byte| *byte == 0) {
+            None
+        } else {
+            Some((index, packet))
+        }
+    })
+}
+
+pub fn players_at(data: &[u8], tick: u32) -> Vec<PlayerTickState> {
+    let Some(header) = DataHeader::unpack(data) else {
+        return Vec::new();
+    };
+    let world = header.world();
+    packets_at::<{ PlayerState::PACKET_SIZE }>(
+        data,
+        &header,
+        header.player_offset(),
+        header.player_count,
+        tick,
+    )
+    .map(|(id, packet)| {
+        let player = PlayerState::unpack(packet, &world);
+        PlayerTickState {
+            id,
+            position: player.position.into(),
+            yaw: player.angle.into(),
+            pitch: player.pitch.into(),
+            health: player.health,
+            team: player.team as u8,
+            class: player.class as u8,
+            charge: player.charge,
+            conditions: player.conditions.0,
+            active_weapon: (player.active_weapon as u32).checked_sub(1),
+            heal_target: player_ref(player.heal_target),
+        }
+    })
+    .collect()
+}
+
+pub fn buildings_at(data: &[u8], tick: u32) -> Vec<BuildingTickState> {
+    let Some(header) = DataHeader::unpack(data) else {
+        return Vec::new();
+    };
+    let world = header.world();
+    packets_at::<{ BuildingState::PACKET_SIZE }>(
+        data,
+        &header,
+        header.building_offset(),
+        header.building_count,
+        tick,
+    )
+    .map(|(slot, packet)| {
+        let building = BuildingState::unpack(packet, &world);
+        BuildingTickState {
+            slot,
+            position: building.position.into(),
+            yaw: building.angle.into(),
+            health: building.health,
+            team: building.team as u8,
+            building_type: building.ty as u8,
+            level: building.level,
+            builder: player_ref(building.builder),
+            flags: building.flags.0,
+            target: player_ref(building.target),
+            shells: building.shells,
+            rockets: building.rockets,
+        }
+    })
+    .collect()
+}
+
+pub fn projectiles_at(data: &[u8], tick: u32) -> Vec<ProjectileTickState> {
+    let Some(header) = DataHeader::unpack(data) else {
+        return Vec::new();
+    };
+    let world = header.world();
+    packets_at::<{ ProjectileState::PACKET_SIZE }>(
+        data,
+        &header,
+        header.projectile_offset(),
+        header.projectile_count,
+        tick,
+    )
+    .map(|(slot, packet)| {
+        let projectile = ProjectileState::unpack(packet, &world);
+        ProjectileTickState {
+            slot,
+            position: projectile.position.into(),
+            yaw: projectile.angle.into(),
+            team: projectile.team as u8,
+            projectile_type: projectile.ty as u8,
+            critical: projectile.critical,
+            owner: player_ref(projectile.owner),
+        }
+    })
+    .collect()
+}
+
+// anything moving further than this in a single tick is considered a teleport, the engine caps
+// velocities at sv_maxvelocity (3500 units/s, about 53 units per tick at 66 tick) plus a margin
+const TELEPORT_DISTANCE: f32 = 60.0;
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Interpolate between two angles in degrees, taking the shortest way around the circle
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    let diff = (b - a + 540.0).rem_euclid(360.0) - 180.0;
+    (a + diff * t).rem_euclid(360.0)
+}
+
+/// Interpolate between two positions `ticks` apart, or `None` if the entity teleported between them
+fn lerp_position(a: XYZ, b: XYZ, t: f32, ticks: u32) -> Option<XYZ> {
+    let max_distance = TELEPORT_DISTANCE * ticks.max(1) as f32;
+    let distance_squared = (b.x - a.x).powi(2) + (b.y - a.y).powi(2) + (b.z - a.z).powi(2);
+    if distance_squared > max_distance * max_distance {
+        return None;
+    }
+    Some(XYZ {
+        x: lerp(a.x, b.x, t),
+        y: lerp(a.y, b.y, t),
+        z: lerp(a.z, b.z, t),
+    })
+}
+
+/// The snapshot ticks before and after a fractional tick, and how far between them the tick is.
+///
+/// The demo doesn't necessarily contain a snapshot for every tick, the ticks in between repeat
+/// the previous snapshot so we need to interpolate between the actual snapshots.
+fn surrounding_snapshots(snapshot_ticks: &[u32], tick: f32) -> (u32, u32, f32) {
+    let tick = tick.max(0.0);
+    let next = snapshot_ticks.partition_point(|snapshot| *snapshot as f32 <= tick);
+    let previous = next
+        .checked_sub(1)
+        .and_then(|index| snapshot_ticks.get(index));
+    match (previous, snapshot_ticks.get(next)) {
+        (Some(&from), Some(&to)) => (from, to, (tick - from as f32) / (to - from) as f32),
+        _ => (tick.floor() as u32, tick.floor() as u32, 0.0),
+    }
+}
+
+/// Interpolated state of all connected players at a fractional tick.
+///
+/// Everything except the position and view angles is taken from the earlier snapshot, players
+/// that respawned or teleported between the two snapshots aren't interpolated
+pub fn players_interpolated(
+    data: &[u8],
+    snapshot_ticks: &[u32],
+    tick: f32,
+) -> Vec<PlayerTickState> {
+    let (tick, next_tick, t) = surrounding_snapshots(snapshot_ticks, tick);
+    let next = players_at(data, next_tick);
+    players_at(data, tick)
+        .into_iter()
+        .map(|player| {
+            let Some(next) = next.iter().find(|next| next.id == player.id) else {
+                return player;
+            };
+            let respawned = (player.health == 0 && next.health > 0)
+                || player.team != next.team
+                || player.class != next.class;
+            match lerp_position(player.position, next.position, t, next_tick - tick) {
+                Some(position) if !respawned => PlayerTickState {
+                    position,
+                    yaw: lerp_angle(player.yaw, next.yaw, t),
+                    pitch: lerp(player.pitch, next.pitch, t),
+                    ..player
+                },
+                _ => player,
+            }
+        })
+        .collect()
+}
+
+/// Interpolated state of all buildings at a fractional tick
+pub fn buildings_interpolated(
+    data: &[u8],
+    snapshot_ticks: &[u32],
+    tick: f32,
+) -> Vec<BuildingTickState> {
+    let (tick, next_tick, t) = surrounding_snapshots(snapshot_ticks, tick);
+    let next = buildings_at(data, next_tick);
+    buildings_at(data, tick)
+        .into_iter()
+        .map(|building| {
+            let Some(next) = next.iter().find(|next| next.slot == building.slot) else {
+                return building;
+            };
+            match lerp_position(building.position, next.position, t, next_tick - tick) {
+                Some(position) => BuildingTickState {
+                    position,
+                    yaw: lerp_angle(building.yaw, next.yaw, t),
+                    ..building
+                },
+                None => building,
+            }
+        })
+        .collect()
+}
+
+/// Interpolated state of all projectiles at a fractional tick
+pub fn projectiles_interpolated(
+    data: &[u8],
+    snapshot_ticks: &[u32],
+    tick: f32,
+) -> Vec<ProjectileTickState> {
+    let (tick, next_tick, t) = surrounding_snapshots(snapshot_ticks, tick);
+    let next = projectiles_at(data, next_tick);
+    projectiles_at(data, tick)
+        .into_iter()
+        .map(|projectile| {
+            let Some(next) = next.iter().find(|next| next.slot == projectile.slot) else {
+                return projectile;
+            };
+            match lerp_position(projectile.position, next.position, t, next_tick - tick) {
+                Some(position) => ProjectileTickState {
+                    position,
+                    yaw: lerp_angle(projectile.yaw, next.yaw, t),
+                    ..projectile
+                },
+                None => projectile,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_players_at() {
+    use crate::state::Angle;
+    use tf_demo_parser::demo::parser::gamestateanalyser::{Class, Team};
+    use tf_demo_parser::demo::vector::Vector;
+
+    let header = DataHeader::new(
+        2,
+        2,
+        0,
+        0,
+        Vector {
+            x: -10000.0,
+            y: -10000.0,
+            z: -100.0,
+        },
+        Vector {
+            x: 10000.0,
+            y: 10000.0,
+            z: 100.0,
+        },
+    );
+    let world = header.world();
+    let player = PlayerState {
+        position: Vector {
+            x: 100.0,
+            y: -5000.0,
+            z: 50.0,
+        },
+        angle: Angle::from(90.0),
+        health: 150,
+        team: Team::Red,
+        class: Class::Medic,
+        heal_target: 1,
+        ..PlayerState::default()
+    };
+
+    let mut data = header.pack().to_vec();
+    // first player only present in the second tick, second player not present at all
+    data.extend_from_slice(&[0; PlayerState::PACKET_SIZE]);
+    data.extend_from_slice(&player.pack(&world));
+    data.extend_from_slice(&[0; PlayerState::PACKET_SIZE * 2]);
+
+    assert_eq!(Vec::<PlayerTickState>::new(), players_at(&data, 0));
+    assert_eq!(Vec::<PlayerTickState>::new(), players_at(&data, 2));
+
+    let players = players_at(&data, 1);
+    assert_eq!(1, players.len());
+    assert_eq!(0, players[0].id);
+    assert_eq!(150, players[0].health);
+    assert_eq!(Class::Medic as u8, players[0].class);
+    assert_eq!(Some(0), players[0].heal_target);
+    assert_eq!(None, players[0].active_weapon);
+    assert!(f32::abs(players[0].position.z - 50.0) < 0.5);
+    assert!(f32::abs(players[0].yaw - 90.0) < 2.0);
+}
+
+#[test]
+fn test_lerp_angle() {
+    assert!(f32::abs(lerp_angle(10.0, 20.0, 0.5) - 15.0) < 0.01);
+    // wraps around 0 instead of going the long way
+    assert!(f32::abs(lerp_angle(350.0, 10.0, 0.5) - 0.0) < 0.01);
+    assert!(f32::abs(lerp_angle(10.0, 350.0, 0.25) - 5.0) < 0.01);
+    assert!(f32::abs(lerp_angle(355.0, 15.0, 0.75) - 10.0) < 0.01);
+}
+
+#[test]
+fn test_lerp_position_teleport() {
+    let a = XYZ {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let b = XYZ {
+        x: 10.0,
+        y: 20.0,
+        z: 0.0,
+    };
+    let far = XYZ {
+        x: 2000.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    assert_eq!(
+        Some(XYZ {
+            x: 5.0,
+            y: 10.0,
+            z: 0.0,
+        }),
+        lerp_position(a, b, 0.5, 1)
+    );
+    assert_eq!(None, lerp_position(a, far, 0.5, 1));
+    // moving the same distance over more ticks isn't a teleport
+    assert!(lerp_position(a, far, 0.5, 40).is_some());
+
+    // a short teleporter trip between snapshots 2 ticks apart
+    let teleporter = XYZ {
+        x: 300.0,
+        y: 400.0,
+        z: 0.0,
+    };
+    assert_eq!(None, lerp_position(a, teleporter, 0.5, 2));
+}
+
+#[test]
+fn test_surrounding_snapshots() {
+    let snapshots = [0, 3, 6, 7];
+
+    assert_eq!((0, 3, 0.0), surrounding_snapshots(&snapshots, 0.0));
+    assert_eq!((0, 3, 0.5), surrounding_snapshots(&snapshots, 1.5));
+    assert_eq!((3, 6, 0.0), surrounding_snapshots(&snapshots, 3.0));
+    assert_eq!((6, 7, 0.25), surrounding_snapshots(&snapshots, 6.25));
+    // after the last snapshot there is nothing to interpolate towards
+    assert_eq!((7, 7, 0.0), surrounding_snapshots(&snapshots, 7.5));
+}
@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, BTreeSet};
+use tf_demo_parser::demo::message::packetentities::EntityId;
+use tf_demo_parser::demo::parser::analyser::UserInfo;
+use tf_demo_parser::demo::parser::gamestateanalyser::{Player, UserId};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerSession {
+    pub user_id: u16,
+    pub entity_id: u32,
+    pub join_tick: u32,
+    /// First tick where the player is no longer connected
+    pub leave_tick: u32,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameChange {
+    pub tick: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RosterEntry {
+    /// The most recent info for the player
+    pub info: UserInfo,
+    pub names: Vec<NameChange>,
+    pub sessions: Vec<PlayerSession>,
+}
+
+impl RosterEntry {
+    fn is_connected(&self) -> bool {
+        self.sessions
+            .last()
+            .is_some_and(|session| session.leave_tick == u32::MAX)
+    }
+
+    fn is_bot(&self) -> bool {
+        is_bot(&self.info)
+    }
+}
+
+fn is_bot(info: &UserInfo) -> bool {
+    info.steam_id.is_empty() || info.steam_id == "BOT"
+}
+
+/// Assigns every player a fixed row in the packed data.
+///
+/// Players are identified by steam id, so a player that reconnects (and gets a new user id)
+/// ends up in the same row.
+#[derive(Debug, Default, Clone)]
+pub struct Roster {
+    entries: Vec<RosterEntry>,
+    // user id -> row for every connected player
+    connected: BTreeMap<u16, usize>,
+    // the parser keeps the player entities of disconnected users around, so we need to ignore those
+    disconnected: BTreeSet<u16>,
+}
+
+impl Roster {
+    /// Get the row for a player, registering any join, reconnect or name change
+    pub fn row(&mut self, info: &UserInfo, tick: u32) -> Option<usize> {
+        let user_id = u16::from(info.user_id);
+        if self.disconnected.contains(&user_id) {
+            return None;
+        }
+
+        if let Some(row) = self.connected.get(&user_id) {
+            let entry = &mut self.entries[*row];
+            if entry.info.name != info.name {
+                entry.info.name = info.name.clone();
+                entry.names.push(NameChange {
+                    tick,
+                    name: info.name.clone(),
+                });
+            }
+            if entry.info.entity_id != info.entity_id {
+                entry.info.entity_id = info.entity_id;
+                if let Some(session) = entry.sessions.last_mut() {
+                    session.entity_id = info.entity_id.into();
+                }
+            }
+            return Some(*row);
+        }
+
+        let session = PlayerSession {
+            user_id,
+            entity_id: info.entity_id.into(),
+            join_tick: tick,
+            leave_tick: u32::MAX,
+        };
+
+        let reconnected = if is_bot(info) {
+            None
+        } else {
+            self.entries.iter().position(|entry| {
+                !entry.is_bot() && !entry.is_connected() && entry.info.steam_id == info.steam_id
+            })
+        };
+
+        let row = match reconnected {
+            Some(row) => {
+                let entry = &mut self.entries[row];
+                if entry.info.name != info.name {
+                    entry.names.push(NameChange {
+                        tick,
+                        name: info.name.clone(),
+                    });
+                }
+                entry.info = info.clone();
+                entry.sessions.push(session);
+                row
+            }
+            None => {
+                self.entries.push(RosterEntry {
+                    info: info.clone(),
+                    names: vec![NameChange {
+                        tick,
+                        name: info.name.clone(),
+                    }],
+                    sessions: vec![session],
+                });
+                self.entries.len() - 1
+            }
+        };
+        self.connected.insert(user_id, row);
+        Some(row)
+    }
+
+    pub fn disconnect(&mut self, user_id: UserId, tick: u32) {
+        let user_id = u16::from(user_id);
+        if let Some(row) = self.connected.remove(&user_id) {
+            if let Some(session) = self.entries[row].sessions.last_mut() {
+                session.leave_tick = tick;
+            }
+        }
+        self.disconnected.insert(user_id);
+    }
+
+    /// Row for a currently connected player
+    pub fn connected_row(&self, user_id: UserId) -> Option<usize> {
+        self.connected.get(&u16::from(user_id)).copied()
+    }
+
+    pub fn player_row(&self, player: &Player) -> Option<usize> {
+        player
+            .info
+            .as_ref()
+            .and_then(|info| self.connected_row(info.user_id))
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Close the sessions of all players still connected at the end of the demo
+    pub fn finish(&mut self, tick: u32) -> Vec<RosterEntry> {
+        let connected: Vec<u16> = self.connected.keys().copied().collect();
+        for user_id in connected {
+            self.disconnect(UserId::from(user_id), tick);
+        }
+        std::mem::take(&mut self.entries)
+    }
+}
+
+/// Lookup from the ids used by the game to the index of a player in the roster, which is the id
+/// used for players everywhere else
+#[derive(Debug, Default, Clone)]
+pub struct PlayerIds {
+    by_user_id: BTreeMap<u16, usize>,
+    sessions: Vec<(usize, PlayerSession)>,
+}
+
+impl PlayerIds {
+    pub fn new(entries: &[RosterEntry]) -> Self {
+        let sessions: Vec<(usize, PlayerSession)> = entries
+            .iter()
+            .enumerate()
+            .flat_map(|(index, entry)| entry.sessions.iter().map(move |session| (index, *session)))
+            .collect();
+        let by_user_id = sessions
+            .iter()
+            .map(|(index, session)| (session.user_id, *index))
+            .collect();
+        PlayerIds {
+            by_user_id,
+            sessions,
+        }
+    }
+
+    pub fn by_user_id(&self, user_id: UserId) -> Option<usize> {
+        self.by_user_id.get(&u16::from(user_id)).copied()
+    }
+
+    /// Entity ids get reused after a player leaves, so we need to know the tick to find the player
+    pub fn by_entity_id(&self, entity_id: EntityId, tick: u32) -> Option<usize> {
+        let entity_id = u32::from(entity_id);
+        self.sessions
+            .iter()
+            .find(|(_, session)| {
+                session.entity_id == entity_id
+                    && session.join_tick <= tick
+                    && tick < session.leave_tick
+            })
+            .map(|(index, _)| *index)
+    }
+}
+
+#[test]
+fn test_roster_reconnect() {
+    let mut roster = Roster::default();
+    let player = |user_id: u16, name: &str, steam_id: &str| UserInfo {
+        name: name.into(),
+        user_id: UserId::from(user_id),
+        steam_id: steam_id.into(),
+        entity_id: EntityId::from(user_id as u32),
+        ..UserInfo::default()
+    };
+
+    assert_eq!(Some(0), roster.row(&player(2, "foo", "[U:1:1]"), 0));
+    assert_eq!(Some(1), roster.row(&player(3, "bar", "[U:1:2]"), 0));
+    assert_eq!(Some(0), roster.row(&player(2, "foo2", "[U:1:1]"), 10));
+
+    roster.disconnect(UserId::from(2), 20);
+    // stale player entity after the disconnect
+    assert_eq!(None, roster.row(&player(2, "foo2", "[U:1:1]"), 21));
+    assert_eq!(None, roster.connected_row(UserId::from(2)));
+
+    // reconnecting with a new user id gets the same row
+    assert_eq!(Some(0), roster.row(&player(4, "foo2", "[U:1:1]"), 30));
+    assert_eq!(Some(0), roster.connected_row(UserId::from(4)));
+
+    // bots never get merged
+    assert_eq!(Some(2), roster.row(&player(5, "bot", "BOT"), 30));
+    roster.disconnect(UserId::from(5), 35);
+    assert_eq!(Some(3), roster.row(&player(6, "bot", "BOT"), 40));
+
+    assert_eq!(4, roster.player_count());
+    let entries = roster.finish(50);
+    assert_eq!(
+        vec![
+            PlayerSession {
+                user_id: 2,
+                entity_id: 2,
+                join_tick: 0,
+                leave_tick: 20,
+            },
+            PlayerSession {
+                user_id: 4,
+                entity_id: 4,
+                join_tick: 30,
+                leave_tick: 50,
+            }
+        ],
+        entries[0].sessions
+    );
+    assert_eq!(
+        vec![
+            NameChange {
+                tick: 0,
+                name: "foo".into(),
+            },
+            NameChange {
+                tick: 10,
+                name: "foo2".into(),
+            }
+        ],
+        entries[0].names
+    );
+    assert_eq!(50, entries[1].sessions[0].leave_tick);
+
+    let ids = PlayerIds::new(&entries);
+    assert_eq!(Some(0), ids.by_user_id(UserId::from(2)));
+    assert_eq!(Some(0), ids.by_user_id(UserId::from(4)));
+    assert_eq!(Some(3), ids.by_user_id(UserId::from(6)));
+    assert_eq!(None, ids.by_user_id(UserId::from(7)));
+    assert_eq!(Some(0), ids.by_entity_id(EntityId::from(2u32), 15));
+    assert_eq!(None, ids.by_entity_id(EntityId::from(2u32), 25));
+}
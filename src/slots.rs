@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use tf_demo_parser::demo::message::packetentities::EntityId;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityLifetime {
+    pub entity_id: u32,
+    pub slot: usize,
+    pub spawn_tick: u32,
+    /// First tick where the entity is no longer present
+    pub despawn_tick: u32,
+}
+
+/// Assigns every entity a fixed row in the packed data for as long as it exists.
+///
+/// A slot only gets reused once it has been empty for at least one tick, so consumers can
+/// always tell two different entities in the same slot apart.
+#[derive(Debug, Default, Clone)]
+pub struct SlotAllocator {
+    // entity id -> index into `lifetimes`
+    active: BTreeMap<EntityId, usize>,
+    // slot -> tick at which it was freed
+    free: BTreeMap<usize, usize>,
+    slot_count: usize,
+    lifetimes: Vec<EntityLifetime>,
+}
+
+impl SlotAllocator {
+    /// Update the allocated slots with the entities present at `tick`
+    pub fn update(&mut self, tick: usize, entities: impl IntoIterator<Item = EntityId>) {
+        let present: Vec<EntityId> = entities.into_iter().collect();
+
+        let lifetimes = &mut self.lifetimes;
+        let free = &mut self.free;
+        self.active.retain(|entity, lifetime_index| {
+            if present.contains(entity) {
+                true
+            } else {
+                let lifetime = &mut lifetimes[*lifetime_index];
+                lifetime.despawn_tick = tick as u32;
+                free.insert(lifetime.slot, tick);
+                false
+            }
+        });
+
+        for entity in present {
+            if self.active.contains_key(&entity) {
+                continue;
+            }
+
+            let reusable = self
+                .free
+                .iter()
+                .find(|(_, freed)| **freed < tick)
+                .map(|(slot, _)| *slot);
+            let slot = match reusable {
+                Some(slot) => {
+                    self.free.remove(&slot);
+                    slot
+                }
+                None => {
+                    self.slot_count += 1;
+                    self.slot_count - 1
+                }
+            };
+
+            self.active.insert(entity, self.lifetimes.len());
+            self.lifetimes.push(EntityLifetime {
+                entity_id: entity.into(),
+                slot,
+                spawn_tick: tick as u32,
+                despawn_tick: u32::MAX,
+            });
+        }
+    }
+
+    pub fn slot(&self, entity: EntityId) -> Option<usize> {
+        self.lifetime(entity).map(|lifetime| lifetime.slot)
+    }
+
+    pub fn lifetime(&self, entity: EntityId) -> Option<&EntityLifetime> {
+        self.active
+            .get(&entity)
+            .map(|lifetime_index| &self.lifetimes[*lifetime_index])
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slot_count
+    }
+
+    /// Close the lifetimes of all entities still alive at the end of the demo
+    pub fn finish(&mut self, tick: usize) -> Vec<EntityLifetime> {
+        self.update(tick, []);
+        std::mem::take(&mut self.lifetimes)
+    }
+}
+
+#[test]
+fn test_slot_allocation() {
+    let mut slots = SlotAllocator::default();
+    let a = EntityId::from(10u32);
+    let b = EntityId::from(11u32);
+    let c = EntityId::from(12u32);
+    let d = EntityId::from(13u32);
+    let e = EntityId::from(14u32);
+
+    slots.update(0, [a, b, c]);
+    assert_eq!(Some(0), slots.slot(a));
+    assert_eq!(Some(1), slots.slot(b));
+    assert_eq!(Some(2), slots.slot(c));
+
+    // removing `a` doesn't shift the others, and its slot isn't reused in the same tick
+    slots.update(1, [b, c, d]);
+    assert_eq!(None, slots.slot(a));
+    assert_eq!(Some(1), slots.slot(b));
+    assert_eq!(Some(2), slots.slot(c));
+    assert_eq!(Some(3), slots.slot(d));
+
+    slots.update(2, [b, c, d, e]);
+    assert_eq!(Some(0), slots.slot(e));
+    assert_eq!(4, slots.slot_count());
+
+    let lifetimes = slots.finish(3);
+    assert_eq!(5, lifetimes.len());
+    assert_eq!(
+        EntityLifetime {
+            entity_id: 10,
+            slot: 0,
+            spawn_tick: 0,
+            despawn_tick: 1,
+        },
+        lifetimes[0]
+    );
+    assert_eq!(
+        EntityLifetime {
+            entity_id: 14,
+            slot: 0,
+            spawn_tick: 2,
+            despawn_tick: 3,
+        },
+        lifetimes[4]
+    );
+}
@@ -1,17 +1,23 @@
+use crate::bounds::Bounds;
+use crate::roster::{PlayerIds, Roster, RosterEntry};
+use crate::slots::{EntityLifetime, SlotAllocator};
+use crate::weapons::WeaponTable;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use tf_demo_parser::demo::data::game_state::{Projectile, ProjectileType};
 use tf_demo_parser::demo::data::DemoTick;
 use tf_demo_parser::demo::gamevent::GameEvent;
 use tf_demo_parser::demo::header::Header;
-use tf_demo_parser::demo::parser::analyser::UserInfo;
+use tf_demo_parser::demo::message::packetentities::EntityId;
 use tf_demo_parser::demo::parser::gamestateanalyser::{
-    Building, Class, Dispenser, GameState, Kill, PlayerState as PlayerAliveState, Sentry, Team,
-    Teleporter, UserId, World,
+    Building, Class, Dispenser, GameState, Handle, Kill, Player, PlayerState as PlayerAliveState,
+    Sentry, Team, Teleporter, UserId, World,
 };
-use tf_demo_parser::demo::vector::VectorXY;
+use tf_demo_parser::demo::vector::Vector;
+use wasm_bindgen::prelude::*;
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
-pub struct Angle(u8);
+pub struct Angle(pub(crate) u8);
 
 impl From<f32> for Angle {
     fn from(val: f32) -> Self {
@@ -27,7 +33,186 @@ impl From<Angle> for f32 {
     }
 }
 
-#[derive(Debug)]
+/// Vertical view angle, limited to -90 (looking up) to 90 (looking down)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pitch(pub(crate) u8);
+
+impl From<f32> for Pitch {
+    fn from(val: f32) -> Self {
+        let ratio = (val.clamp(-90.0, 90.0) + 90.0) / 180.0;
+        Pitch((ratio * u8::MAX as f32).round() as u8)
+    }
+}
+
+impl From<Pitch> for f32 {
+    fn from(val: Pitch) -> Self {
+        let ratio = val.0 as f32 / u8::MAX as f32;
+        ratio * 180.0 - 90.0
+    }
+}
+
+/// Unit vector in the direction a player with the given yaw and pitch (in degrees) is looking
+pub fn view_direction(yaw: f32, pitch: f32) -> Vector {
+    let (yaw_sin, yaw_cos) = yaw.to_radians().sin_cos();
+    let (pitch_sin, pitch_cos) = pitch.to_radians().sin_cos();
+    Vector {
+        x: pitch_cos * yaw_cos,
+        y: pitch_cos * yaw_sin,
+        z: -pitch_sin,
+    }
+}
+
+#[test]
+fn test_view_direction() {
+    let forward = view_direction(0.0, 0.0);
+    assert!(f32::abs(forward.x - 1.0) < 0.001);
+    assert!(f32::abs(forward.y) < 0.001);
+    assert!(f32::abs(forward.z) < 0.001);
+
+    let left = view_direction(90.0, 0.0);
+    assert!(f32::abs(left.x) < 0.001);
+    assert!(f32::abs(left.y - 1.0) < 0.001);
+
+    let down = view_direction(123.0, 90.0);
+    assert!(f32::abs(down.x) < 0.001);
+    assert!(f32::abs(down.y) < 0.001);
+    assert!(f32::abs(down.z + 1.0) < 0.001);
+}
+
+/// Player conditions included in the packed player state, the value is the bit in the packed flags
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerCondition {
+    Ubercharged = 0,
+    Kritzkrieged = 1,
+    Cloaked = 2,
+    Disguised = 3,
+    Burning = 4,
+    Jarated = 5,
+}
+
+impl PlayerCondition {
+    const ALL: [PlayerCondition; 6] = [
+        PlayerCondition::Ubercharged,
+        PlayerCondition::Kritzkrieged,
+        PlayerCondition::Cloaked,
+        PlayerCondition::Disguised,
+        PlayerCondition::Burning,
+        PlayerCondition::Jarated,
+    ];
+
+    /// The `ETFCond` value used by the game
+    fn game_condition(&self) -> usize {
+        match self {
+            PlayerCondition::Ubercharged => 5,
+            PlayerCondition::Kritzkrieged => 11,
+            PlayerCondition::Cloaked => 4,
+            PlayerCondition::Disguised => 3,
+            PlayerCondition::Burning => 22,
+            PlayerCondition::Jarated => 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Conditions(pub(crate) u8);
+
+impl Conditions {
+    /// Pick the conditions we care about from the game's condition bits
+    pub fn from_game_conditions(conditions: &[u8]) -> Self {
+        let flags = PlayerCondition::ALL
+            .iter()
+            .filter(|cond| {
+                let game_cond = cond.game_condition();
+                conditions
+                    .get(game_cond / 8)
+                    .is_some_and(|byte| byte & (1 << (game_cond % 8)) != 0)
+            })
+            .fold(0, |flags, cond| flags | (1 << *cond as u8));
+        Conditions(flags)
+    }
+
+    #[allow(dead_code)]
+    pub fn has(&self, cond: PlayerCondition) -> bool {
+        self.0 & (1 << cond as u8) != 0
+    }
+}
+
+#[test]
+fn test_conditions() {
+    // burning and ubercharged
+    let game_conditions = [1 << 5, 0, 1 << 6, 0];
+    let conditions = Conditions::from_game_conditions(&game_conditions);
+    assert!(conditions.has(PlayerCondition::Ubercharged));
+    assert!(conditions.has(PlayerCondition::Burning));
+    assert!(!conditions.has(PlayerCondition::Cloaked));
+    assert!(!conditions.has(PlayerCondition::Jarated));
+}
+
+// references to players are packed as the index of the player plus one, with 0 meaning "no player",
+// players that don't fit are packed as "no player" instead of being confused with another player
+fn pack_player_index(index: Option<usize>) -> u8 {
+    index
+        .and_then(|index| index.checked_add(1))
+        .and_then(|packed| u8::try_from(packed).ok())
+        .unwrap_or(0)
+}
+
+pub(crate) fn unpack_player_index(packed: u8) -> Option<usize> {
+    (packed as usize).checked_sub(1)
+}
+
+#[test]
+fn test_pack_player_index() {
+    assert_eq!(None, unpack_player_index(pack_player_index(None)));
+    assert_eq!(Some(0), unpack_player_index(pack_player_index(Some(0))));
+    assert_eq!(Some(254), unpack_player_index(pack_player_index(Some(254))));
+    assert_eq!(None, unpack_player_index(pack_player_index(Some(255))));
+    assert_eq!(
+        None,
+        unpack_player_index(pack_player_index(Some(usize::MAX)))
+    );
+}
+
+fn player_by_entity(game_state: &GameState, entity: EntityId) -> Option<&Player> {
+    game_state.players.iter().find(|player| {
+        player
+            .info
+            .as_ref()
+            .is_some_and(|info| info.entity_id == entity)
+    })
+}
+
+fn player_by_weapon(game_state: &GameState, weapon: Handle) -> Option<&Player> {
+    game_state
+        .players
+        .iter()
+        .find(|player| player.weapons.contains(&weapon))
+}
+
+/// The states of all entities in a single tick, before they're packed
+#[derive(Debug, Clone, Default)]
+struct TickStates {
+    players: Vec<(usize, PlayerState)>,
+    buildings: Vec<(usize, BuildingState)>,
+    projectiles: Vec<(usize, ProjectileState)>,
+}
+
+/// Add the packet for an entity at `tick`, padding any ticks the entity wasn't present with zeros
+fn push_packet(rows: &mut Vec<Vec<u8>>, row: usize, tick: usize, packet: &[u8], tick_count: usize) {
+    while rows.len() <= row {
+        rows.push(Vec::with_capacity(tick_count * packet.len()));
+    }
+
+    let parsed = &mut rows[row];
+    parsed.resize(tick * packet.len(), 0);
+    parsed.extend_from_slice(packet);
+}
+
+// 66 ticks per second, the tick rate used by tf2 servers
+const DEFAULT_INTERVAL_PER_TICK: f32 = 0.015;
+
+#[derive(Debug, Clone)]
 pub struct ParsedDemo {
     last_tick: DemoTick,
     pub tick: usize,
@@ -37,9 +222,34 @@ pub struct ParsedDemo {
     pub kills: Vec<Kill>,
     pub events: Vec<SearchableEvent>,
     pub header: Header,
-    pub player_info: Vec<UserInfo>,
-    pub max_building_count: usize,
-    pub max_projectile_count: usize,
+    pub roster: Roster,
+    pub roster_entries: Vec<RosterEntry>,
+    pub player_ids: PlayerIds,
+    processed_events: usize,
+    pub building_slots: SlotAllocator,
+    pub projectile_slots: SlotAllocator,
+    pub building_lifetimes: Vec<EntityLifetime>,
+    pub projectile_lifetimes: Vec<EntityLifetime>,
+    pub weapons: WeaponTable,
+    /// Number of ticks each (medic, target) pair has been healing
+    pub heal_ticks: BTreeMap<(usize, usize), u32>,
+    /// Number of projectiles fired by each player
+    pub projectile_counts: Vec<u32>,
+    /// Parsing stopped before the end of the demo
+    pub truncated: bool,
+    /// Tick interval from the server info, for demos without a finished header
+    pub server_interval_per_tick: Option<f32>,
+    /// Packed ticks that contain a new snapshot, other ticks repeat the snapshot before them
+    pub snapshot_ticks: Vec<u32>,
+    /// Ticks that can't be packed until the world boundaries are known
+    pending: Vec<TickStates>,
+    /// Bounds of all positions seen, used when the demo has no world
+    bounds: Bounds,
+    /// The boundaries the positions are packed against
+    pub world: Option<World>,
+    /// Pack against the bounds of the observed positions instead of the world, for better
+    /// precision at the cost of keeping every tick in memory until the end
+    pub tight_bounds: bool,
 }
 
 impl ParsedDemo {
@@ -51,104 +261,194 @@ impl ParsedDemo {
             buildings: Vec::new(),
             projectiles: Vec::new(),
             kills: Vec::new(),
-            player_info: Vec::new(),
-            max_building_count: 0,
-            max_projectile_count: 0,
+            roster: Roster::default(),
+            roster_entries: Vec::new(),
+            player_ids: PlayerIds::default(),
+            processed_events: 0,
+            building_slots: SlotAllocator::default(),
+            projectile_slots: SlotAllocator::default(),
+            building_lifetimes: Vec::new(),
+            projectile_lifetimes: Vec::new(),
+            weapons: WeaponTable::default(),
+            heal_ticks: BTreeMap::new(),
+            projectile_counts: Vec::new(),
+            truncated: false,
+            server_interval_per_tick: None,
+            snapshot_ticks: Vec::new(),
+            pending: Vec::new(),
+            bounds: Bounds::default(),
+            world: None,
+            tight_bounds: false,
             events: Vec::new(),
             header,
         }
     }
 
     pub fn push_state(&mut self, game_state: &GameState) {
-        if let Some(world) = game_state.world.as_ref() {
-            for _tick in u32::from(self.last_tick)..u32::from(game_state.tick) {
-                for (index, player) in game_state.players.iter().enumerate() {
-                    let state = PlayerState {
-                        position: player.position.into(),
-                        angle: Angle::from(player.view_angle),
-                        health: if player.state == PlayerAliveState::Alive {
-                            player.health
-                        } else {
-                            0
-                        },
-                        team: player.team,
-                        class: player.class,
-                        charge: player.charge,
-                    };
-
-                    if self.players.get(index).is_none() {
-                        let mut new_player = Vec::with_capacity(
-                            self.header.ticks as usize * PlayerState::PACKET_SIZE,
-                        );
-                        // backfill with defaults
-                        new_player.resize(self.tick * PlayerState::PACKET_SIZE, 0);
-                        self.players.push(new_player);
-                    };
-
-                    if let (None, Some(info)) = (self.player_info.get(index), player.info.as_ref())
-                    {
-                        self.player_info.push(info.clone());
-                    }
-
-                    let parsed_player = &mut self.players[index];
-                    parsed_player.extend_from_slice(&state.pack(world));
+        for (_, event) in &game_state.events[self.processed_events..] {
+            if let GameEvent::PlayerDisconnect(event) = event {
+                self.roster
+                    .disconnect(UserId::from(event.user_id), self.tick as u32);
+            }
+        }
+        self.processed_events = game_state.events.len();
+
+        let first_tick = self.tick;
+        for _tick in u32::from(self.last_tick)..u32::from(game_state.tick) {
+            let mut states = TickStates::default();
+
+            for player in game_state.players.iter() {
+                let Some(row) = player
+                    .info
+                    .as_ref()
+                    .and_then(|info| self.roster.row(info, self.tick as u32))
+                else {
+                    continue;
+                };
+
+                let heal_target = player
+                    .heal_target
+                    .and_then(|target| player_by_entity(game_state, target))
+                    .and_then(|target| self.roster.player_row(target));
+                if let Some(target) = heal_target {
+                    *self.heal_ticks.entry((row, target)).or_default() += 1;
                 }
 
-                self.max_building_count = self.max_building_count.max(game_state.buildings.len());
-                for (index, building) in game_state.buildings.values().enumerate() {
-                    let state = BuildingState::new(building);
-
-                    if self.buildings.get(index).is_none() {
-                        let new_building = Vec::with_capacity(
-                            self.header.ticks as usize * BuildingState::PACKET_SIZE,
-                        );
-                        self.buildings.push(new_building);
-                    };
+                let state = PlayerState {
+                    position: player.position,
+                    angle: Angle::from(player.view_angle),
+                    pitch: Pitch::from(player.pitch_angle),
+                    health: if player.state == PlayerAliveState::Alive {
+                        player.health
+                    } else {
+                        0
+                    },
+                    team: player.team,
+                    class: player.class,
+                    charge: player.charge,
+                    conditions: Conditions::from_game_conditions(&player.conditions),
+                    active_weapon: self.weapons.packed_index(player.active_weapon, game_state),
+                    heal_target: pack_player_index(heal_target),
+                };
+                self.bounds.add_player(&state);
+                states.players.push((row, state));
+            }
 
-                    let parsed_building = &mut self.buildings[index];
-                    parsed_building.resize(self.tick * BuildingState::PACKET_SIZE, 0);
+            self.building_slots
+                .update(self.tick, game_state.buildings.keys().copied());
+            for (entity_id, building) in game_state.buildings.iter() {
+                let state = BuildingState::new(building, &self.roster);
+                let Some(slot) = self.building_slots.slot(*entity_id) else {
+                    continue;
+                };
+                self.bounds.add(state.position);
+                states.buildings.push((slot, state));
+            }
 
-                    parsed_building.extend_from_slice(&state.pack(world));
+            self.projectile_slots
+                .update(self.tick, game_state.projectiles.keys().copied());
+            for (entity_id, projectile) in game_state.projectiles.iter() {
+                let owner = player_by_weapon(game_state, projectile.launcher)
+                    .and_then(|player| self.roster.player_row(player));
+                let state = ProjectileState::new(projectile, owner);
+                let Some(lifetime) = self.projectile_slots.lifetime(*entity_id) else {
+                    continue;
+                };
+
+                let spawned = lifetime.spawn_tick as usize == self.tick;
+                if let Some(owner) = owner.filter(|_| spawned) {
+                    if self.projectile_counts.len() <= owner {
+                        self.projectile_counts.resize(owner + 1, 0);
+                    }
+                    self.projectile_counts[owner] += 1;
                 }
 
-                self.max_projectile_count =
-                    self.max_projectile_count.max(game_state.projectiles.len());
-                for (index, projectile) in game_state.projectiles.values().enumerate() {
-                    let state = ProjectileState::new(projectile);
+                self.bounds.add(state.position);
+                states.projectiles.push((lifetime.slot, state));
+            }
 
-                    if self.projectiles.get(index).is_none() {
-                        let new_projectile = Vec::with_capacity(
-                            self.header.ticks as usize * ProjectileState::PACKET_SIZE,
-                        );
-                        self.projectiles.push(new_projectile);
-                    };
+            self.pending.push(states);
+            self.tick += 1;
+        }
+        self.last_tick = game_state.tick;
+        // the ticks we just added all contain the same snapshot, the last one is the closest to
+        // the time the snapshot was taken
+        if self.tick > first_tick {
+            self.snapshot_ticks.push(self.tick as u32 - 1);
+        }
 
-                    let parsed_projectiles = &mut self.projectiles[index];
-                    parsed_projectiles.resize(self.tick * ProjectileState::PACKET_SIZE, 0);
+        if let Some(world) = game_state.world.as_ref().filter(|_| !self.tight_bounds) {
+            self.pack_pending(world);
+        }
+    }
 
-                    parsed_projectiles.extend_from_slice(&state.pack(world));
-                }
-                self.tick += 1;
+    /// Pack all ticks that have been waiting for the world boundaries
+    fn pack_pending(&mut self, world: &World) {
+        let first_tick = self.tick - self.pending.len();
+        let tick_count = self.header.ticks as usize;
+        for (tick, states) in (first_tick..).zip(std::mem::take(&mut self.pending)) {
+            for (row, state) in states.players {
+                let packet = state.pack(world);
+                push_packet(&mut self.players, row, tick, &packet, tick_count);
+            }
+            for (slot, state) in states.buildings {
+                let packet = state.pack(world);
+                push_packet(&mut self.buildings, slot, tick, &packet, tick_count);
+            }
+            for (slot, state) in states.projectiles {
+                let packet = state.pack(world);
+                push_packet(&mut self.projectiles, slot, tick, &packet, tick_count);
             }
-            self.last_tick = game_state.tick;
         }
     }
 
     pub fn finish(&mut self, state: &GameState) {
+        // without a world in the demo, fall back to the area the entities have been in
+        self.world = if self.tight_bounds {
+            self.bounds.world().or_else(|| state.world.clone())
+        } else {
+            state.world.clone().or_else(|| self.bounds.world())
+        };
+        if let Some(world) = self.world.clone() {
+            self.pack_pending(&world);
+        }
+
+        self.players
+            .resize_with(self.roster.player_count(), Vec::new);
+        self.roster_entries = self.roster.finish(self.tick as u32);
+        self.player_ids = PlayerIds::new(&self.roster_entries);
+        for parsed_player in self.players.iter_mut() {
+            parsed_player.resize(self.tick * PlayerState::PACKET_SIZE, 0);
+        }
         for parsed_building in self.buildings.iter_mut() {
             parsed_building.resize(self.tick * BuildingState::PACKET_SIZE, 0);
         }
         for parsed_projectiles in self.projectiles.iter_mut() {
             parsed_projectiles.resize(self.tick * ProjectileState::PACKET_SIZE, 0);
         }
+        self.building_lifetimes = self.building_slots.finish(self.tick);
+        self.projectile_lifetimes = self.projectile_slots.finish(self.tick);
 
         self.events = state
             .events
             .iter()
-            .flat_map(|(tick, event)| SearchableEvent::from_event(*tick, event))
+            .flat_map(|(tick, event)| SearchableEvent::from_event(*tick, event, &self.player_ids))
             .collect();
     }
 
+    /// Seconds per tick.
+    ///
+    /// Demos that weren't properly finished (e.g. because the server crashed) don't have the
+    /// duration and tick count in their header, so those use the interval from the server info
+    pub fn interval_per_tick(&self) -> f32 {
+        if self.header.ticks > 0 && self.header.duration > 0.0 {
+            self.header.duration / self.header.ticks as f32
+        } else {
+            self.server_interval_per_tick
+                .unwrap_or(DEFAULT_INTERVAL_PER_TICK)
+        }
+    }
+
     pub fn size(&self) -> usize {
         self.players
             .iter()
@@ -158,16 +458,20 @@ impl ParsedDemo {
 
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct PlayerState {
-    position: VectorXY,
-    angle: Angle,
-    health: u16,
-    team: Team,
-    class: Class,
-    charge: u8,
+    pub(crate) position: Vector,
+    pub(crate) angle: Angle,
+    pub(crate) pitch: Pitch,
+    pub(crate) health: u16,
+    pub(crate) team: Team,
+    pub(crate) class: Class,
+    pub(crate) charge: u8,
+    pub(crate) conditions: Conditions,
+    pub(crate) active_weapon: u16,
+    pub(crate) heal_target: u8,
 }
 
 impl PlayerState {
-    const PACKET_SIZE: usize = 8;
+    pub const PACKET_SIZE: usize = 15;
 
     pub fn pack(&self, world: &World) -> [u8; Self::PACKET_SIZE] {
         // for the purpose of viewing the demo in the browser we dont really need high accuracy for
@@ -180,27 +484,35 @@ impl PlayerState {
 
         let x = pack_f32(self.position.x, world.boundary_min.x, world.boundary_max.x).to_le_bytes();
         let y = pack_f32(self.position.y, world.boundary_min.y, world.boundary_max.y).to_le_bytes();
+        let z = pack_f32(self.position.z, world.boundary_min.z, world.boundary_max.z).to_le_bytes();
         // 2 bits for team
         // 4 bits for class
         // 10 bits for health
         let team_class_health =
             ((self.team as u16) << 14) + ((self.class as u16) << 10) + self.health;
         let combined_bytes = team_class_health.to_le_bytes();
+        let active_weapon = self.active_weapon.to_le_bytes();
 
         [
             x[0],
             x[1],
             y[0],
             y[1],
+            z[0],
+            z[1],
             combined_bytes[0],
             combined_bytes[1],
             self.angle.0,
+            self.pitch.0,
             self.charge,
+            self.conditions.0,
+            active_weapon[0],
+            active_weapon[1],
+            self.heal_target,
         ]
     }
 
-    #[allow(dead_code)]
-    pub fn unpack(bytes: [u8; 8], world: &World) -> Self {
+    pub fn unpack(bytes: [u8; Self::PACKET_SIZE], world: &World) -> Self {
         fn unpack_f32(val: u16, min: f32, max: f32) -> f32 {
             let ratio = val as f32 / (u16::MAX as f32);
             ratio * (max - min) + min
@@ -216,28 +528,39 @@ impl PlayerState {
             world.boundary_min.y,
             world.boundary_max.y,
         );
-        let team_class_health = u16::from_le_bytes([bytes[4], bytes[5]]);
+        let z = unpack_f32(
+            u16::from_le_bytes([bytes[4], bytes[5]]),
+            world.boundary_min.z,
+            world.boundary_max.z,
+        );
+        let team_class_health = u16::from_le_bytes([bytes[6], bytes[7]]);
         let health = team_class_health & 1023;
-        let angle = Angle(bytes[6]);
+        let angle = Angle(bytes[8]);
+        let pitch = Pitch(bytes[9]);
         let team = Team::new(team_class_health >> 14);
         let class = Class::new((team_class_health >> 10) & 15);
-        let charge = bytes[7];
+        let charge = bytes[10];
+        let conditions = Conditions(bytes[11]);
+        let active_weapon = u16::from_le_bytes([bytes[12], bytes[13]]);
+        let heal_target = bytes[14];
 
         PlayerState {
-            position: VectorXY { x, y },
+            position: Vector { x, y, z },
             angle,
+            pitch,
             health,
             team,
             class,
             charge,
+            conditions,
+            active_weapon,
+            heal_target,
         }
     }
 }
 
 #[test]
 fn test_player_packing() {
-    use tf_demo_parser::demo::vector::Vector;
-
     let world = World {
         boundary_max: Vector {
             x: 10000.0,
@@ -252,28 +575,39 @@ fn test_player_packing() {
     };
 
     let input = PlayerState {
-        position: VectorXY {
+        position: Vector {
             x: 100.0,
             y: -5000.0,
+            z: 50.0,
         },
         angle: Angle::from(213.0),
+        pitch: Pitch::from(-45.0),
         health: 250,
         team: Team::Blue,
         class: Class::Demoman,
         charge: 7,
+        conditions: Conditions(0b100101),
+        active_weapon: 12,
+        heal_target: pack_player_index(Some(3)),
     };
 
     let bytes = input.pack(&world);
 
     let unpacked = PlayerState::unpack(bytes, &world);
     assert_eq!(input.angle, unpacked.angle);
+    assert_eq!(input.pitch, unpacked.pitch);
+    assert!(f32::abs(f32::from(unpacked.pitch) + 45.0) < 1.0);
     assert_eq!(input.health, unpacked.health);
     assert_eq!(input.class, unpacked.class);
     assert_eq!(input.team, unpacked.team);
     assert_eq!(input.charge, unpacked.charge);
+    assert_eq!(input.conditions, unpacked.conditions);
+    assert_eq!(input.active_weapon, unpacked.active_weapon);
+    assert_eq!(Some(3), unpack_player_index(unpacked.heal_target));
 
     assert!(f32::abs(input.position.x - unpacked.position.x) < 0.5);
     assert!(f32::abs(input.position.y - unpacked.position.y) < 0.5);
+    assert!(f32::abs(input.position.z - unpacked.position.z) < 0.5);
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
@@ -334,14 +668,74 @@ impl BuildingType {
     }
 }
 
+/// Building status included in the packed building state, the value is the bit in the packed flags
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildingStatus {
+    Constructing = 0,
+    Sapped = 1,
+    Carried = 2,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BuildingFlags(pub(crate) u8);
+
+impl BuildingFlags {
+    pub fn from_building(building: &Building) -> Self {
+        let (constructing, sapped, carried) = match building {
+            Building::Sentry(Sentry {
+                building: constructing,
+                sapped,
+                carried,
+                ..
+            })
+            | Building::Dispenser(Dispenser {
+                building: constructing,
+                sapped,
+                carried,
+                ..
+            })
+            | Building::Teleporter(Teleporter {
+                building: constructing,
+                sapped,
+                carried,
+                ..
+            }) => (*constructing, *sapped, *carried),
+        };
+
+        let mut flags = 0;
+        for (status, set) in [
+            (BuildingStatus::Constructing, constructing),
+            (BuildingStatus::Sapped, sapped),
+            (BuildingStatus::Carried, carried),
+        ] {
+            if set {
+                flags |= 1 << status as u8;
+            }
+        }
+        BuildingFlags(flags)
+    }
+
+    #[allow(dead_code)]
+    pub fn has(&self, status: BuildingStatus) -> bool {
+        self.0 & (1 << status as u8) != 0
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct BuildingState {
-    position: VectorXY,
-    angle: Angle,
-    health: u16,
-    team: Team,
-    ty: BuildingType,
-    level: u8,
+    pub(crate) position: Vector,
+    pub(crate) angle: Angle,
+    pub(crate) health: u16,
+    pub(crate) team: Team,
+    pub(crate) ty: BuildingType,
+    pub(crate) level: u8,
+    pub(crate) builder: u8,
+    pub(crate) flags: BuildingFlags,
+    // sentry only
+    pub(crate) target: u8,
+    pub(crate) shells: u8,
+    pub(crate) rockets: u8,
 }
 
 // for the purpose of viewing the demo in the browser we dont really need high accuracy for
@@ -357,26 +751,42 @@ fn unpack_f32(val: u16, min: f32, max: f32) -> f32 {
 }
 
 impl BuildingState {
-    const PACKET_SIZE: usize = 7;
+    pub const PACKET_SIZE: usize = 14;
+
+    pub fn new(building: &Building, roster: &Roster) -> Self {
+        let (target, shells, rockets) = match building {
+            Building::Sentry(Sentry {
+                auto_aim_target,
+                shells,
+                rockets,
+                ..
+            }) => (
+                pack_player_index(roster.connected_row(*auto_aim_target)),
+                (*shells).min(u8::MAX as u16) as u8,
+                (*rockets).min(u8::MAX as u16) as u8,
+            ),
+            _ => (0, 0, 0),
+        };
 
-    pub fn new(building: &Building) -> Self {
-        let position = building.position();
         BuildingState {
-            position: VectorXY {
-                x: position.x,
-                y: position.y,
-            },
+            position: building.position(),
             angle: Angle::from(building.angle()),
             health: building.health(),
             team: building.team(),
             ty: BuildingType::from_building(building),
             level: building.level(),
+            builder: pack_player_index(roster.connected_row(building.builder())),
+            flags: BuildingFlags::from_building(building),
+            target,
+            shells,
+            rockets,
         }
     }
 
     pub fn pack(&self, world: &World) -> [u8; Self::PACKET_SIZE] {
         let x = pack_f32(self.position.x, world.boundary_min.x, world.boundary_max.x).to_le_bytes();
         let y = pack_f32(self.position.y, world.boundary_min.y, world.boundary_max.y).to_le_bytes();
+        let z = pack_f32(self.position.z, world.boundary_min.z, world.boundary_max.z).to_le_bytes();
         // 2 bits level
         // 1 bit team
         // 3 bits for type
@@ -393,13 +803,19 @@ impl BuildingState {
             x[1],
             y[0],
             y[1],
+            z[0],
+            z[1],
             combined_bytes[0],
             combined_bytes[1],
             self.angle.0,
+            self.builder,
+            self.flags.0,
+            self.target,
+            self.shells,
+            self.rockets,
         ]
     }
 
-    #[allow(dead_code)]
     pub fn unpack(bytes: [u8; Self::PACKET_SIZE], world: &World) -> Self {
         let x = unpack_f32(
             u16::from_le_bytes([bytes[0], bytes[1]]),
@@ -411,9 +827,14 @@ impl BuildingState {
             world.boundary_min.y,
             world.boundary_max.y,
         );
-        let team_type_health = u16::from_le_bytes([bytes[4], bytes[5]]);
+        let z = unpack_f32(
+            u16::from_le_bytes([bytes[4], bytes[5]]),
+            world.boundary_min.z,
+            world.boundary_max.z,
+        );
+        let team_type_health = u16::from_le_bytes([bytes[6], bytes[7]]);
         let health = team_type_health & 1023;
-        let angle = Angle(bytes[6]);
+        let angle = Angle(bytes[8]);
         let packed_team = (team_type_health >> 13) & 1;
         let team = if packed_team == 0 {
             Team::Blue
@@ -422,22 +843,30 @@ impl BuildingState {
         };
         let ty = BuildingType::new((team_type_health >> 10) as u8 & 7);
         let level = (team_type_health >> 14) as u8;
+        let builder = bytes[9];
+        let flags = BuildingFlags(bytes[10]);
+        let target = bytes[11];
+        let shells = bytes[12];
+        let rockets = bytes[13];
 
         BuildingState {
-            position: VectorXY { x, y },
+            position: Vector { x, y, z },
             angle,
             health,
             team,
             ty,
             level,
+            builder,
+            flags,
+            target,
+            shells,
+            rockets,
         }
     }
 }
 
 #[test]
 fn test_building_packing() {
-    use tf_demo_parser::demo::vector::Vector;
-
     let world = World {
         boundary_max: Vector {
             x: 10000.0,
@@ -452,15 +881,21 @@ fn test_building_packing() {
     };
 
     let input = BuildingState {
-        position: VectorXY {
+        position: Vector {
             x: 100.0,
             y: -5000.0,
+            z: 50.0,
         },
         angle: Angle::from(213.0),
         health: 250,
         team: Team::Blue,
         level: 3,
         ty: BuildingType::Level1Sentry,
+        builder: pack_player_index(Some(5)),
+        flags: BuildingFlags(0b101),
+        target: pack_player_index(Some(0)),
+        shells: 144,
+        rockets: 20,
     };
 
     let bytes = input.pack(&world);
@@ -471,48 +906,66 @@ fn test_building_packing() {
     assert_eq!(input.ty, unpacked.ty);
     assert_eq!(input.team, unpacked.team);
     assert_eq!(input.level, unpacked.level);
+    assert_eq!(Some(5), unpack_player_index(unpacked.builder));
+    assert!(unpacked.flags.has(BuildingStatus::Constructing));
+    assert!(!unpacked.flags.has(BuildingStatus::Sapped));
+    assert!(unpacked.flags.has(BuildingStatus::Carried));
+    assert_eq!(Some(0), unpack_player_index(unpacked.target));
+    assert_eq!(input.shells, unpacked.shells);
+    assert_eq!(input.rockets, unpacked.rockets);
 
     assert!(f32::abs(input.position.x - unpacked.position.x) < 0.5);
     assert!(f32::abs(input.position.y - unpacked.position.y) < 0.5);
+    assert!(f32::abs(input.position.z - unpacked.position.z) < 0.5);
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct ProjectileState {
-    position: VectorXY,
-    team: Team,
-    ty: ProjectileType,
-    angle: Angle,
+    pub(crate) position: Vector,
+    pub(crate) team: Team,
+    pub(crate) ty: ProjectileType,
+    pub(crate) angle: Angle,
+    pub(crate) critical: bool,
+    pub(crate) owner: u8,
 }
 
 impl ProjectileState {
-    const PACKET_SIZE: usize = 6;
+    pub const PACKET_SIZE: usize = 9;
 
-    pub fn new(projectile: &Projectile) -> Self {
-        let position = projectile.position;
+    pub fn new(projectile: &Projectile, owner: Option<usize>) -> Self {
         ProjectileState {
-            position: VectorXY {
-                x: position.x,
-                y: position.y,
-            },
+            position: projectile.position,
             angle: Angle::from(projectile.rotation.y),
             team: projectile.team,
             ty: projectile.ty,
+            critical: projectile.critical,
+            owner: pack_player_index(owner),
         }
     }
 
     pub fn pack(&self, world: &World) -> [u8; Self::PACKET_SIZE] {
         let x = pack_f32(self.position.x, world.boundary_min.x, world.boundary_max.x).to_le_bytes();
         let y = pack_f32(self.position.y, world.boundary_min.y, world.boundary_max.y).to_le_bytes();
-        // 1 bit team
+        let z = pack_f32(self.position.z, world.boundary_min.z, world.boundary_max.z).to_le_bytes();
         // 3 bits for type
-        // 4 bits for angle, 16 angles should be enough for projectiles
+        // 1 bit team
+        // 1 bit critical
         let team = if self.team == Team::Blue { 0 } else { 1 };
-        let team_type = ((self.ty as u8) << 5) + ((team as u8) << 4);
+        let team_type = ((self.ty as u8) << 5) + ((team as u8) << 4) + ((self.critical as u8) << 3);
 
-        [x[0], x[1], y[0], y[1], team_type, self.angle.0]
+        [
+            x[0],
+            x[1],
+            y[0],
+            y[1],
+            z[0],
+            z[1],
+            team_type,
+            self.angle.0,
+            self.owner,
+        ]
     }
 
-    #[allow(dead_code)]
     pub fn unpack(bytes: [u8; Self::PACKET_SIZE], world: &World) -> Self {
         let x = unpack_f32(
             u16::from_le_bytes([bytes[0], bytes[1]]),
@@ -524,7 +977,12 @@ impl ProjectileState {
             world.boundary_min.y,
             world.boundary_max.y,
         );
-        let team_type = bytes[4];
+        let z = unpack_f32(
+            u16::from_le_bytes([bytes[4], bytes[5]]),
+            world.boundary_min.z,
+            world.boundary_max.z,
+        );
+        let team_type = bytes[6];
         let packed_team = (team_type >> 4) & 1;
         let team = if packed_team == 0 {
             Team::Blue
@@ -532,21 +990,23 @@ impl ProjectileState {
             Team::Red
         };
         let ty = ProjectileType::from((team_type >> 5) & 7);
-        let angle = Angle(bytes[5]);
+        let critical = (team_type >> 3) & 1 == 1;
+        let angle = Angle(bytes[7]);
+        let owner = bytes[8];
 
         ProjectileState {
-            position: VectorXY { x, y },
+            position: Vector { x, y, z },
             angle,
             team,
             ty,
+            critical,
+            owner,
         }
     }
 }
 
 #[test]
 fn test_projectile_packing() {
-    use tf_demo_parser::demo::vector::Vector;
-
     let world = World {
         boundary_max: Vector {
             x: 10000.0,
@@ -561,13 +1021,16 @@ fn test_projectile_packing() {
     };
 
     let input = ProjectileState {
-        position: VectorXY {
+        position: Vector {
             x: 100.0,
             y: -5000.0,
+            z: 50.0,
         },
         angle: Angle::from(123.0),
         team: Team::Blue,
         ty: ProjectileType::Flare,
+        critical: true,
+        owner: pack_player_index(Some(11)),
     };
 
     let bytes = input.pack(&world);
@@ -576,12 +1039,16 @@ fn test_projectile_packing() {
     assert_eq!(input.ty, unpacked.ty);
     assert_eq!(input.team, unpacked.team);
     assert_eq!(input.angle, unpacked.angle);
+    assert!(unpacked.critical);
+    assert_eq!(Some(11), unpack_player_index(unpacked.owner));
 
     assert!(f32::abs(input.position.x - unpacked.position.x) < 0.5);
     assert!(f32::abs(input.position.y - unpacked.position.y) < 0.5);
+    assert!(f32::abs(input.position.z - unpacked.position.z) < 0.5);
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[wasm_bindgen]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum RawBuildingType {
     Dispenser,
@@ -607,14 +1074,14 @@ impl TryFrom<u16> for RawBuildingType {
 #[serde(rename_all = "snake_case")]
 pub enum SearchableEvent {
     Uber {
-        user_id: UserId,
-        target_id: UserId,
+        player_id: Option<usize>,
+        target_id: Option<usize>,
         tick: DemoTick,
     },
     BuildingDestroyed {
-        attacker_id: UserId,
-        assister_id: UserId,
-        victim_id: UserId,
+        attacker_id: Option<usize>,
+        assister_id: Option<usize>,
+        victim_id: Option<usize>,
         weapon: String,
         building_type: RawBuildingType,
         tick: DemoTick,
@@ -622,25 +1089,115 @@ pub enum SearchableEvent {
 }
 
 impl SearchableEvent {
-    pub fn from_event(tick: DemoTick, event: &GameEvent) -> Option<SearchableEvent> {
+    pub fn from_event(
+        tick: DemoTick,
+        event: &GameEvent,
+        player_ids: &PlayerIds,
+    ) -> Option<SearchableEvent> {
+        let player_id = |user_id: u16| player_ids.by_user_id(UserId::from(user_id));
         match event {
             GameEvent::ObjectDestroyed(event) => {
                 let building_type = RawBuildingType::try_from(event.object_type).ok()?;
                 Some(SearchableEvent::BuildingDestroyed {
-                    attacker_id: UserId::from(event.attacker),
-                    assister_id: UserId::from(event.assister),
-                    victim_id: UserId::from(event.user_id),
+                    attacker_id: player_id(event.attacker),
+                    assister_id: player_id(event.assister),
+                    victim_id: player_id(event.user_id),
                     weapon: event.weapon.to_string(),
                     building_type,
                     tick,
                 })
             }
             GameEvent::PlayerChargeDeployed(event) => Some(SearchableEvent::Uber {
-                user_id: UserId::from(event.user_id),
-                target_id: UserId::from(event.target_id),
+                player_id: player_id(event.user_id),
+                target_id: player_id(event.target_id),
                 tick,
             }),
             _ => None,
         }
     }
 }
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemoEventType {
+    Uber,
+    BuildingDestroyed,
+}
+
+/// JS facing version of [`SearchableEvent`], fields that don't apply to the event type are undefined
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemoEvent {
+    #[wasm_bindgen(js_name = "type")]
+    pub ty: DemoEventType,
+    pub tick: u32,
+    /// The player deploying the uber or destroying the building
+    pub player_id: Option<u32>,
+    /// The uber target or the owner of the destroyed building
+    pub target_id: Option<u32>,
+    pub assister_id: Option<u32>,
+    pub weapon: Option<String>,
+    pub building_type: Option<RawBuildingType>,
+}
+
+impl From<&SearchableEvent> for DemoEvent {
+    fn from(event: &SearchableEvent) -> Self {
+        let id = |id: &Option<usize>| id.map(|id| id as u32);
+        match event {
+            SearchableEvent::Uber {
+                player_id,
+                target_id,
+                tick,
+            } => DemoEvent {
+                ty: DemoEventType::Uber,
+                tick: u32::from(*tick),
+                player_id: id(player_id),
+                target_id: id(target_id),
+                assister_id: None,
+                weapon: None,
+                building_type: None,
+            },
+            SearchableEvent::BuildingDestroyed {
+                attacker_id,
+                assister_id,
+                victim_id,
+                weapon,
+                building_type,
+                tick,
+            } => DemoEvent {
+                ty: DemoEventType::BuildingDestroyed,
+                tick: u32::from(*tick),
+                player_id: id(attacker_id),
+                target_id: id(victim_id),
+                assister_id: id(assister_id),
+                weapon: Some(weapon.clone()),
+                building_type: Some(*building_type),
+            },
+        }
+    }
+}
+
+#[test]
+fn test_event_conversion() {
+    let event = SearchableEvent::BuildingDestroyed {
+        attacker_id: Some(1),
+        assister_id: None,
+        victim_id: Some(4),
+        weapon: "tf_projectile_rocket".into(),
+        building_type: RawBuildingType::SentryGun,
+        tick: DemoTick::from(1234u32),
+    };
+
+    assert_eq!(
+        DemoEvent {
+            ty: DemoEventType::BuildingDestroyed,
+            tick: 1234,
+            player_id: Some(1),
+            target_id: Some(4),
+            assister_id: None,
+            weapon: Some("tf_projectile_rocket".into()),
+            building_type: Some(RawBuildingType::SentryGun),
+        },
+        DemoEvent::from(&event)
+    );
+}
@@ -0,0 +1,359 @@
+use crate::error::{is_out_of_data, DemoError};
+use crate::state::ParsedDemo;
+use tf_demo_parser::demo::header::Header;
+use tf_demo_parser::demo::message::Message;
+use tf_demo_parser::demo::packet::Packet;
+use tf_demo_parser::demo::parser::gamestateanalyser::GameStateAnalyser;
+use tf_demo_parser::demo::parser::{DemoHandler, Parse};
+use tf_demo_parser::{Demo, ParseError, Stream};
+use wasm_bindgen::prelude::*;
+
+const HEADER_SIZE: usize = 1072;
+const DEMO_MAGIC: &[u8; 8] = b"HL2DEMO\0";
+const DEMO_PROTOCOL: u32 = 3;
+
+/// Parser that can be driven a bit at a time, either by feeding it the demo in chunks while it's
+/// being downloaded or by parsing a limited number of ticks per step.
+///
+/// Packets are parsed as soon as all their bytes are available, since parsing a packet doesn't
+/// modify the parser state, a packet that is cut off can simply be retried once more data arrives.
+#[wasm_bindgen]
+pub struct StreamingParser {
+    // data that hasn't been parsed yet
+    stream: Stream<'static>,
+    // number of bytes before the start of `stream`
+    offset: usize,
+    // data received after `stream` was created
+    pending: Vec<u8>,
+    // don't try parsing again until this many bytes are available
+    retry_size: usize,
+    handler: DemoHandler<'static, GameStateAnalyser>,
+    parsed: Option<ParsedDemo>,
+    ended: bool,
+    tight_bounds: bool,
+}
+
+impl StreamingParser {
+    pub fn new() -> Self {
+        Self::with_data(Vec::new())
+    }
+
+    /// Parser for a demo that is already fully available
+    pub fn with_data(data: Vec<u8>) -> Self {
+        StreamingParser {
+            stream: Demo::owned(data).get_stream(),
+            offset: 0,
+            pending: Vec::new(),
+            retry_size: HEADER_SIZE,
+            handler: DemoHandler::with_analyser(GameStateAnalyser::default()),
+            parsed: None,
+            ended: false,
+            tight_bounds: false,
+        }
+    }
+
+    /// Pack positions against the bounds of the observed positions, see `ParsedDemo::tight_bounds`
+    pub fn with_tight_bounds(mut self, tight_bounds: bool) -> Self {
+        self.tight_bounds = tight_bounds;
+        self
+    }
+
+    /// Add more data and parse as far as the available data allows
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), DemoError> {
+        self.pending.extend_from_slice(chunk);
+        if self.stream.bits_left() / 8 + self.pending.len() < self.retry_size {
+            return Ok(());
+        }
+        self.load_pending()?;
+        self.parse(false, u32::MAX)
+    }
+
+    /// Move the pending data into the stream, together with the data that hasn't been parsed yet
+    fn load_pending(&mut self) -> Result<(), DemoError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.offset += self.stream.pos() / 8;
+        let mut data = self
+            .stream
+            .read_bytes(self.stream.bits_left() / 8)
+            .map_err(|e| self.error(e.into()))?
+            .into_owned();
+        data.append(&mut self.pending);
+        self.stream = Demo::owned(data).get_stream();
+        Ok(())
+    }
+
+    /// Parse at most `max_ticks` more ticks, returns `true` once the stop packet at the end of the
+    /// demo is reached.
+    ///
+    /// All data is expected to be available, so running out of data is an error.
+    pub fn step(&mut self, max_ticks: u32) -> Result<bool, DemoError> {
+        self.load_pending()?;
+        self.parse(true, max_ticks)?;
+        Ok(self.ended)
+    }
+
+    /// The last tick that has been parsed
+    pub fn tick(&self) -> usize {
+        self.parsed.as_ref().map(|parsed| parsed.tick).unwrap_or(0)
+    }
+
+    /// Percentage of the demo that has been parsed
+    pub fn progress(&self) -> f32 {
+        match &self.parsed {
+            _ if self.ended => 100.0,
+            Some(parsed) if parsed.header.ticks > 0 => {
+                (demo_tick(&self.handler) as f32 / parsed.header.ticks as f32 * 100.0).min(100.0)
+            }
+            // unfinished demos don't have the tick count in the header, go by the data instead
+            _ => {
+                let parsed = self.offset + self.stream.pos() / 8;
+                let total = parsed + self.stream.bits_left() / 8 + self.pending.len();
+                if total > 0 {
+                    parsed as f32 / total as f32 * 100.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// Add the position of the parser to an error
+    fn error(&self, error: ParseError) -> DemoError {
+        DemoError::Parse {
+            error,
+            tick: demo_tick(&self.handler),
+            offset: self.offset + self.stream.pos() / 8,
+            partial: self.tick() > 0,
+        }
+    }
+
+    /// When `complete` is false, running out of data means we need to wait for more
+    fn parse(&mut self, complete: bool, max_ticks: u32) -> Result<(), DemoError> {
+        if self.ended {
+            return Ok(());
+        }
+
+        if self.parsed.is_none() {
+            if !complete && self.stream.bits_left() < HEADER_SIZE * 8 {
+                return Ok(());
+            }
+            self.check_magic()?;
+            let header = Header::read(&mut self.stream).map_err(|e| self.error(e))?;
+            // the parser only knows one demo protocol
+            if header.version != DEMO_PROTOCOL {
+                return Err(DemoError::UnsupportedProtocol(header.version));
+            }
+            self.handler.handle_header(&header);
+            let mut parsed = ParsedDemo::new(header);
+            parsed.tight_bounds = self.tight_bounds;
+            self.parsed = Some(parsed);
+        }
+
+        self.parse_packets(complete, max_ticks)
+            .map_err(|e| self.error(e))
+    }
+
+    /// Check that the data starts with the demo magic, before trying to parse it as a header
+    fn check_magic(&mut self) -> Result<(), DemoError> {
+        let available = (self.stream.bits_left() / 8).min(DEMO_MAGIC.len());
+        let start = self.stream.pos();
+        let magic = self
+            .stream
+            .read_bytes(available)
+            .map_err(|e| self.error(e.into()))?;
+        let is_demo = magic.as_ref() == &DEMO_MAGIC[..available];
+        self.stream
+            .set_pos(start)
+            .map_err(|e| self.error(e.into()))?;
+
+        if is_demo {
+            Ok(())
+        } else {
+            Err(DemoError::NotADemo)
+        }
+    }
+
+    fn parse_packets(&mut self, complete: bool, max_ticks: u32) -> Result<(), ParseError> {
+        let Some(parsed) = self.parsed.as_mut() else {
+            return Ok(());
+        };
+
+        let end_tick = demo_tick(&self.handler).saturating_add(max_ticks);
+        while demo_tick(&self.handler) < end_tick {
+            // the demo only ends at a stop packet, running out of data before that means the demo
+            // has been truncated
+            let start = self.stream.pos();
+            match Packet::parse(&mut self.stream, self.handler.get_parser_state()) {
+                Ok(packet) => {
+                    let is_stop = matches!(packet, Packet::Stop(_));
+                    if let Packet::Signon(message_packet) | Packet::Message(message_packet) =
+                        &packet
+                    {
+                        for message in &message_packet.messages {
+                            if let Message::ServerInfo(info) = message {
+                                parsed.server_interval_per_tick = Some(info.interval_per_tick);
+                            }
+                        }
+                    }
+                    self.handler.handle_packet(packet)?;
+                    parsed.push_state(self.handler.borrow_output());
+                    if is_stop {
+                        self.ended = true;
+                        break;
+                    }
+                }
+                Err(e) => {
+                    // report errors at the start of the packet
+                    self.stream.set_pos(start)?;
+                    if complete || !is_out_of_data(&e) {
+                        return Err(e);
+                    }
+                    // the packet is cut off and gets parsed again from the start once more data
+                    // arrives, waiting until the data has doubled keeps the total amount of
+                    // re-copying and re-parsing linear in the size of the demo
+                    self.retry_size = 2 * (self.stream.bits_left() / 8);
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finish up what has been parsed so far, without stopping the parser.
+    ///
+    /// This copies everything parsed so far, so it's meant for starting playback once, not for
+    /// calling after every chunk
+    pub fn snapshot(&self) -> Option<ParsedDemo> {
+        let mut parsed = self.parsed.clone()?;
+        let state = self.handler.borrow_output();
+        parsed.finish(state);
+        parsed.kills = state.kills.clone();
+        Some(parsed)
+    }
+
+    /// Parse all remaining data, now that all data is available
+    pub fn finish(mut self) -> Result<ParsedDemo, DemoError> {
+        self.load_pending()?;
+        self.parse(true, u32::MAX)?;
+        Ok(self
+            .finish_partial()
+            .expect("header is always parsed once all data is available"))
+    }
+
+    /// Finish with only what has been parsed so far, for demos that can't be parsed any further.
+    ///
+    /// The result is marked as truncated if the end of the demo hasn't been reached
+    pub fn finish_partial(self) -> Option<ParsedDemo> {
+        let mut parsed = self.parsed?;
+        parsed.truncated = !self.ended;
+        parsed.finish(self.handler.borrow_output());
+
+        let state = self.handler.into_output();
+        parsed.kills = state.kills;
+        Some(parsed)
+    }
+}
+
+fn demo_tick(handler: &DemoHandler<'static, GameStateAnalyser>) -> u32 {
+    handler.borrow_output().tick.into()
+}
+
+impl Default for StreamingParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+pub fn demo_header(ticks: u32) -> Vec<u8> {
+    let mut header = DEMO_MAGIC.to_vec();
+    header.extend_from_slice(&DEMO_PROTOCOL.to_le_bytes());
+    header.extend_from_slice(&24u32.to_le_bytes());
+    for name in ["server", "nick", "map", "tf"] {
+        let mut field = [0; 260];
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        header.extend_from_slice(&field);
+    }
+    header.extend_from_slice(&(ticks as f32 * 0.015).to_le_bytes());
+    header.extend_from_slice(&ticks.to_le_bytes());
+    header.extend_from_slice(&ticks.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    assert_eq!(HEADER_SIZE, header.len());
+    header
+}
+
+#[cfg(test)]
+pub fn sync_tick_packet(tick: u32) -> Vec<u8> {
+    let mut packet = vec![3];
+    packet.extend_from_slice(&tick.to_le_bytes());
+    packet
+}
+
+#[test]
+fn test_streaming_below_header() {
+    let mut parser = StreamingParser::new();
+    parser.push(&demo_header(0)[..100]).unwrap();
+    assert!(parser.parsed.is_none());
+    assert!(parser.snapshot().is_none());
+}
+
+#[test]
+fn test_streaming_not_a_demo() {
+    let mut parser = StreamingParser::new();
+    let result = parser.push(&[0; HEADER_SIZE + 10]);
+    assert!(matches!(result, Err(DemoError::NotADemo)));
+}
+
+#[test]
+fn test_small_non_demo() {
+    let mut parser = StreamingParser::with_data(b"<html>".to_vec());
+    assert!(matches!(parser.step(u32::MAX), Err(DemoError::NotADemo)));
+
+    // the start of a demo is truncated, not something else
+    let mut parser = StreamingParser::with_data(b"HL2DE".to_vec());
+    let error = parser.step(u32::MAX).unwrap_err();
+    assert_eq!(crate::error::ParseErrorKind::Truncated, error.kind());
+}
+
+#[test]
+fn test_unsupported_protocol() {
+    let mut data = demo_header(0);
+    data[8..12].copy_from_slice(&4u32.to_le_bytes());
+    let mut parser = StreamingParser::with_data(data);
+    assert!(matches!(
+        parser.step(u32::MAX),
+        Err(DemoError::UnsupportedProtocol(4))
+    ));
+}
+
+#[test]
+fn test_streaming_split_packet() {
+    let mut data = demo_header(0);
+    data.extend_from_slice(&sync_tick_packet(5));
+    let (first, second) = data.split_at(HEADER_SIZE + 2);
+
+    let mut parser = StreamingParser::new();
+    parser.push(first).unwrap();
+    assert!(parser.parsed.is_some());
+    // the start of the packet is kept until the rest of it arrives
+    assert_eq!(2, parser.stream.bits_left() / 8);
+
+    parser.push(second).unwrap();
+    assert_eq!(0, parser.stream.bits_left() / 8);
+    assert!(parser.pending.is_empty());
+}
+
+#[test]
+fn test_stepped_without_stop_is_truncated() {
+    let mut data = demo_header(0);
+    data.extend_from_slice(&sync_tick_packet(5));
+
+    // cut on a packet boundary, but without the stop packet
+    let mut parser = StreamingParser::with_data(data);
+    let result = parser.step(u32::MAX);
+    assert!(matches!(result, Err(DemoError::Parse { .. })));
+    assert!(parser.finish_partial().unwrap().truncated);
+}
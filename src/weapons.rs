@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use tf_demo_parser::demo::parser::gamestateanalyser::{GameState, Handle};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeaponInfo {
+    pub item_definition: u32,
+    pub class_name: String,
+}
+
+/// All distinct weapons seen in the demo.
+///
+/// Weapons are referenced in the packed player state by their index in this table plus one,
+/// a packed value of 0 means the player has no (known) active weapon
+#[derive(Debug, Default, Clone)]
+pub struct WeaponTable {
+    weapons: Vec<WeaponInfo>,
+    handles: HashMap<Handle, u16>,
+}
+
+impl WeaponTable {
+    pub fn packed_index(&mut self, handle: Handle, game_state: &GameState) -> u16 {
+        if let Some(index) = self.handles.get(&handle) {
+            return *index;
+        }
+
+        // the weapon entity might not be known yet, don't cache the miss so we pick it up once it is
+        let Some(weapon) = game_state.weapons.get(&handle) else {
+            return 0;
+        };
+        let info = WeaponInfo {
+            item_definition: weapon.id,
+            class_name: game_state
+                .server_classes
+                .get(usize::from(weapon.class))
+                .map(|class| class.name.to_string())
+                .unwrap_or_default(),
+        };
+
+        let index = self.insert(info);
+        self.handles.insert(handle, index);
+        index
+    }
+
+    fn insert(&mut self, info: WeaponInfo) -> u16 {
+        let index = match self.weapons.iter().position(|known| *known == info) {
+            Some(index) => index,
+            None => {
+                self.weapons.push(info);
+                self.weapons.len() - 1
+            }
+        };
+        index as u16 + 1
+    }
+
+    pub fn into_weapons(self) -> Vec<WeaponInfo> {
+        self.weapons
+    }
+}
+
+#[test]
+fn test_weapon_table_dedup() {
+    let mut table = WeaponTable::default();
+    let medigun = WeaponInfo {
+        item_definition: 29,
+        class_name: "CWeaponMedigun".into(),
+    };
+    let crossbow = WeaponInfo {
+        item_definition: 305,
+        class_name: "CTFCrossbow".into(),
+    };
+
+    assert_eq!(1, table.insert(medigun.clone()));
+    assert_eq!(2, table.insert(crossbow.clone()));
+    // same weapon from a different entity
+    assert_eq!(1, table.insert(medigun.clone()));
+
+    assert_eq!(vec![medigun, crossbow], table.into_weapons());
+}